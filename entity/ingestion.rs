@@ -6,12 +6,17 @@ use serde::{Deserialize, Serialize};
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
 #[sea_orm(table_name = "ingestion")]
 pub struct Model {
-    #[sea_orm(primary_key)]
-    pub id: i32,
-    pub substance_id: i32,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: String,
+    #[sea_orm(unique)]
+    pub alias: i32,
+    pub substance_id: String,
     pub dosage_unit: String,
     #[sea_orm(column_type = "Double")]
     pub dosage_value: f64,
+    pub route_of_administration: Option<String>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub notes: Option<String>,
     pub ingested_at: DateTimeWithTimeZone,
     pub created_at: DateTimeWithTimeZone,
     pub updated_at: DateTimeWithTimeZone,
@@ -2,5 +2,6 @@
 
 pub mod prelude;
 
+pub mod dosage_limit;
 pub mod ingestion;
 pub mod substance;
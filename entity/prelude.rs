@@ -1,5 +1,6 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.0.0-rc.7
 
+pub use super::dosage_limit::Entity as DosageLimit;
 pub use super::ingestion::Entity as Ingestion;
 pub use super::substance::Entity as Substance;
 
@@ -6,17 +6,31 @@ use serde::{Deserialize, Serialize};
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
 #[sea_orm(table_name = "substance")]
 pub struct Model {
-    #[sea_orm(primary_key)]
-    pub id: i32,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: String,
+    #[sea_orm(unique)]
+    pub alias: i32,
     pub name: String,
+    #[serde(default)]
+    pub duration_minutes: Option<i32>,
+    #[serde(default)]
+    pub redose_interval_minutes: Option<i32>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::dosage_limit::Entity")]
+    DosageLimit,
     #[sea_orm(has_many = "super::ingestion::Entity")]
     Ingestion,
 }
 
+impl Related<super::dosage_limit::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::DosageLimit.def()
+    }
+}
+
 impl Related<super::ingestion::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Ingestion.def()
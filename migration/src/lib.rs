@@ -2,12 +2,23 @@ pub use sea_orm_migration::prelude::*;
 
 mod m20220101_000001_create_table;
 mod m20240728_000001_create_ingestion_table;
+mod m20240901_000001_globally_unique_identifiers;
+mod m20240915_000001_add_ingestion_route_and_notes;
+mod m20241001_000001_create_dosage_limit_table;
+mod m20241015_000001_add_substance_duration_and_redose_interval;
 
 pub struct Migrator;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(m20220101_000001_create_table::Migration), Box::new(m20240728_000001_create_ingestion_table::Migration)]
+        vec![
+            Box::new(m20220101_000001_create_table::Migration),
+            Box::new(m20240728_000001_create_ingestion_table::Migration),
+            Box::new(m20240901_000001_globally_unique_identifiers::Migration),
+            Box::new(m20240915_000001_add_ingestion_route_and_notes::Migration),
+            Box::new(m20241001_000001_create_dosage_limit_table::Migration),
+            Box::new(m20241015_000001_add_substance_duration_and_redose_interval::Migration),
+        ]
     }
 }
@@ -0,0 +1,338 @@
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::prelude::DateTimeWithTimeZone;
+use sea_orm_migration::sea_orm::ConnectionTrait;
+use ulid::Ulid;
+
+/// Replaces autoincrement integer primary keys with ULIDs so journals from
+/// different machines can be merged without collisions. Previous integer
+/// identifiers are preserved as `alias` column which is used to reference
+/// rows from command line.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        let backend = manager.get_database_backend();
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(SubstanceNext::Table)
+                    .col(ColumnDef::new(Substance::Id).string().not_null().primary_key())
+                    .col(ColumnDef::new(Substance::Alias).integer().not_null().unique_key())
+                    .col(ColumnDef::new(Substance::Name).string().not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(IngestionNext::Table)
+                    .col(ColumnDef::new(Ingestion::Id).string().not_null().primary_key())
+                    .col(ColumnDef::new(Ingestion::Alias).integer().not_null().unique_key())
+                    .col(ColumnDef::new(Ingestion::SubstanceId).string().not_null())
+                    .col(ColumnDef::new(Ingestion::DosageUnit).string().not_null())
+                    .col(ColumnDef::new(Ingestion::DosageValue).double().not_null())
+                    .col(ColumnDef::new(Ingestion::IngestedAt).timestamp_with_time_zone().not_null())
+                    .col(ColumnDef::new(Ingestion::CreatedAt).timestamp_with_time_zone().not_null())
+                    .col(ColumnDef::new(Ingestion::UpdatedAt).timestamp_with_time_zone().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_ingestion_substance")
+                            .from(IngestionNext::Table, Ingestion::SubstanceId)
+                            .to(SubstanceNext::Table, Substance::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::NoAction),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        let substances = db
+            .query_all(
+                backend.build(
+                    Query::select()
+                        .columns([Substance::Id, Substance::Name])
+                        .from(Substance::Table)
+                        .order_by(Substance::Id, Order::Asc),
+                ),
+            )
+            .await?;
+
+        let mut substance_identifiers: HashMap<i32, String> = HashMap::new();
+
+        for row in substances {
+            let alias: i32 = row.try_get("", "id")?;
+            let name: String = row.try_get("", "name")?;
+            let id = Ulid::new().to_string();
+
+            db.execute(
+                backend.build(
+                    Query::insert()
+                        .into_table(SubstanceNext::Table)
+                        .columns([Substance::Id, Substance::Alias, Substance::Name])
+                        .values_panic([id.clone().into(), alias.into(), name.into()]),
+                ),
+            )
+            .await?;
+
+            substance_identifiers.insert(alias, id);
+        }
+
+        let ingestions = db
+            .query_all(
+                backend.build(
+                    Query::select()
+                        .columns([
+                            Ingestion::Id,
+                            Ingestion::SubstanceId,
+                            Ingestion::DosageUnit,
+                            Ingestion::DosageValue,
+                            Ingestion::IngestedAt,
+                            Ingestion::CreatedAt,
+                            Ingestion::UpdatedAt,
+                        ])
+                        .from(Ingestion::Table)
+                        .order_by(Ingestion::Id, Order::Asc),
+                ),
+            )
+            .await?;
+
+        for row in ingestions {
+            let alias: i32 = row.try_get("", "id")?;
+            let substance_alias: i32 = row.try_get("", "substance_id")?;
+            let dosage_unit: String = row.try_get("", "dosage_unit")?;
+            let dosage_value: f64 = row.try_get("", "dosage_value")?;
+            let ingested_at: DateTimeWithTimeZone = row.try_get("", "ingested_at")?;
+            let created_at: DateTimeWithTimeZone = row.try_get("", "created_at")?;
+            let updated_at: DateTimeWithTimeZone = row.try_get("", "updated_at")?;
+
+            let substance_id = substance_identifiers
+                .get(&substance_alias)
+                .cloned()
+                .ok_or_else(|| {
+                    DbErr::Migration(format!(
+                        "Ingestion {} references missing substance {}",
+                        alias, substance_alias
+                    ))
+                })?;
+
+            // Keep ordering of identifiers consistent with the time rows were recorded at.
+            let id = Ulid::from_datetime(SystemTime::from(created_at)).to_string();
+
+            db.execute(
+                backend.build(
+                    Query::insert()
+                        .into_table(IngestionNext::Table)
+                        .columns([
+                            Ingestion::Id,
+                            Ingestion::Alias,
+                            Ingestion::SubstanceId,
+                            Ingestion::DosageUnit,
+                            Ingestion::DosageValue,
+                            Ingestion::IngestedAt,
+                            Ingestion::CreatedAt,
+                            Ingestion::UpdatedAt,
+                        ])
+                        .values_panic([
+                            id.into(),
+                            alias.into(),
+                            substance_id.into(),
+                            dosage_unit.into(),
+                            dosage_value.into(),
+                            ingested_at.into(),
+                            created_at.into(),
+                            updated_at.into(),
+                        ]),
+                ),
+            )
+            .await?;
+        }
+
+        manager
+            .drop_table(Table::drop().table(Ingestion::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Substance::Table).to_owned())
+            .await?;
+
+        // SQLite rewrites foreign key references of `ingestion_next` while renaming the parent table.
+        manager
+            .rename_table(
+                Table::rename()
+                    .table(SubstanceNext::Table, Substance::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .rename_table(
+                Table::rename()
+                    .table(IngestionNext::Table, Ingestion::Table)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        let backend = manager.get_database_backend();
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(SubstanceNext::Table)
+                    .col(
+                        ColumnDef::new(Substance::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Substance::Name).string().not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(IngestionNext::Table)
+                    .col(
+                        ColumnDef::new(Ingestion::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Ingestion::SubstanceId).integer().not_null())
+                    .col(ColumnDef::new(Ingestion::DosageUnit).string().not_null())
+                    .col(ColumnDef::new(Ingestion::DosageValue).double().not_null())
+                    .col(ColumnDef::new(Ingestion::IngestedAt).timestamp_with_time_zone().not_null())
+                    .col(ColumnDef::new(Ingestion::CreatedAt).timestamp_with_time_zone().not_null())
+                    .col(ColumnDef::new(Ingestion::UpdatedAt).timestamp_with_time_zone().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_ingestion_substance")
+                            .from(IngestionNext::Table, Ingestion::SubstanceId)
+                            .to(SubstanceNext::Table, Substance::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::NoAction),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        db.execute(
+            backend.build(
+                &Query::insert()
+                    .into_table(SubstanceNext::Table)
+                    .columns([Substance::Id, Substance::Name])
+                    .select_from(
+                        Query::select()
+                            .columns([Substance::Alias, Substance::Name])
+                            .from(Substance::Table)
+                            .to_owned(),
+                    )
+                    .map_err(|err| DbErr::Migration(err.to_string()))?
+                    .to_owned(),
+            ),
+        )
+        .await?;
+
+        db.execute(
+            backend.build(
+                &Query::insert()
+                    .into_table(IngestionNext::Table)
+                    .columns([
+                        Ingestion::Id,
+                        Ingestion::SubstanceId,
+                        Ingestion::DosageUnit,
+                        Ingestion::DosageValue,
+                        Ingestion::IngestedAt,
+                        Ingestion::CreatedAt,
+                        Ingestion::UpdatedAt,
+                    ])
+                    .select_from(
+                        Query::select()
+                            .column((Ingestion::Table, Ingestion::Alias))
+                            .column((Substance::Table, Substance::Alias))
+                            .columns([
+                                (Ingestion::Table, Ingestion::DosageUnit),
+                                (Ingestion::Table, Ingestion::DosageValue),
+                                (Ingestion::Table, Ingestion::IngestedAt),
+                                (Ingestion::Table, Ingestion::CreatedAt),
+                                (Ingestion::Table, Ingestion::UpdatedAt),
+                            ])
+                            .from(Ingestion::Table)
+                            .inner_join(
+                                Substance::Table,
+                                Expr::col((Substance::Table, Substance::Id))
+                                    .equals((Ingestion::Table, Ingestion::SubstanceId)),
+                            )
+                            .to_owned(),
+                    )
+                    .map_err(|err| DbErr::Migration(err.to_string()))?
+                    .to_owned(),
+            ),
+        )
+        .await?;
+
+        manager
+            .drop_table(Table::drop().table(Ingestion::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Substance::Table).to_owned())
+            .await?;
+        manager
+            .rename_table(
+                Table::rename()
+                    .table(SubstanceNext::Table, Substance::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .rename_table(
+                Table::rename()
+                    .table(IngestionNext::Table, Ingestion::Table)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Substance {
+    Table,
+    Id,
+    Alias,
+    Name,
+}
+
+#[derive(DeriveIden)]
+enum SubstanceNext {
+    Table,
+}
+
+#[derive(DeriveIden)]
+enum Ingestion {
+    Table,
+    Id,
+    Alias,
+    SubstanceId,
+    DosageUnit,
+    DosageValue,
+    IngestedAt,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(DeriveIden)]
+enum IngestionNext {
+    Table,
+}
@@ -0,0 +1,64 @@
+use sea_orm_migration::prelude::*;
+
+/// Limits of consumption of substance in a day, week or month, either as
+/// maximal amount (`max_amount` in `unit`) or as maximal number of days with
+/// any ingestion (`max_days`).
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(DosageLimit::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(DosageLimit::Id).string().not_null().primary_key())
+                    .col(ColumnDef::new(DosageLimit::Alias).integer().not_null().unique_key())
+                    .col(ColumnDef::new(DosageLimit::SubstanceId).string().not_null())
+                    .col(ColumnDef::new(DosageLimit::Period).string().not_null())
+                    .col(ColumnDef::new(DosageLimit::MaxAmount).double().null())
+                    .col(ColumnDef::new(DosageLimit::Unit).string().null())
+                    .col(ColumnDef::new(DosageLimit::MaxDays).integer().null())
+                    .col(ColumnDef::new(DosageLimit::CreatedAt).timestamp_with_time_zone().not_null())
+                    .col(ColumnDef::new(DosageLimit::UpdatedAt).timestamp_with_time_zone().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_dosage_limit_substance")
+                            .from(DosageLimit::Table, DosageLimit::SubstanceId)
+                            .to(Substance::Table, Substance::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::NoAction),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(DosageLimit::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum DosageLimit {
+    Table,
+    Id,
+    Alias,
+    SubstanceId,
+    Period,
+    MaxAmount,
+    Unit,
+    MaxDays,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Substance {
+    Table,
+    Id,
+}
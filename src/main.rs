@@ -1,547 +1,7771 @@
+use std::process::ExitCode;
+
 use async_std::task;
+use clap::CommandFactory;
+use clap_complete::CompleteEnv;
 use miette::set_panic_hook;
 
+mod error {
+    use miette::Diagnostic;
+    use sea_orm::DbErr;
+    use thiserror::Error;
+
+    #[derive(Debug, Error, Diagnostic)]
+    pub(crate) enum Error {
+        #[error("{entity} {reference} was not found")]
+        #[diagnostic(code(neuronek::record_not_found))]
+        RecordNotFound { entity: &'static str, reference: String },
+
+        #[error("Substance \"{0}\" was not found")]
+        #[diagnostic(
+            code(neuronek::unknown_substance),
+            help("Substances can be referenced by alias, identifier or name, see `substance list` for known ones or add it with `substance create --name <NAME>`")
+        )]
+        UnknownSubstance(String),
+
+        #[error("Invalid dose: {0}")]
+        #[diagnostic(
+            code(neuronek::invalid_dose),
+            help("Dosage amount has to be a positive number, ex. `--dosage-amount 100 --dosage-unit mg`")
+        )]
+        InvalidDose(String),
+
+        #[error("{0}")]
+        #[diagnostic(
+            code(neuronek::limit_exceeded),
+            help("Ingestion can be saved anyway with `--force`, or set `limit_policy = \"warn\"` in configuration to be only warned")
+        )]
+        LimitExceeded(String),
+
+        #[error("{0}")]
+        #[diagnostic(code(neuronek::invalid_input))]
+        InvalidInput(String),
+
+        #[error("{0}")]
+        #[diagnostic(code(neuronek::invalid_operation))]
+        InvalidOperation(String),
+
+        #[error("{0}")]
+        #[diagnostic(
+            code(neuronek::configuration),
+            help("Effective configuration and its sources are printed by `config show`")
+        )]
+        Configuration(String),
+
+        #[error("{0}")]
+        #[diagnostic(
+            code(neuronek::encryption),
+            help("Passphrase is read from NEURONEK_PASSPHRASE environment variable or prompted for")
+        )]
+        Encryption(String),
+
+        #[error("{0}")]
+        #[diagnostic(code(neuronek::serialization))]
+        Serialization(String),
+
+        #[error("{context}")]
+        #[diagnostic(code(neuronek::io))]
+        Io {
+            context: String,
+            #[source]
+            source: std::io::Error,
+        },
+
+        #[error("Journal database is locked")]
+        #[diagnostic(
+            code(neuronek::database_locked),
+            help("Journal is used by another process, try again once it finishes")
+        )]
+        DatabaseLocked(#[source] DbErr),
+
+        #[error("Failed to migrate journal database")]
+        #[diagnostic(
+            code(neuronek::migration_failed),
+            help("Journal was backed up before migration, backups can be brought back with `restore <FILE>`")
+        )]
+        MigrationFailed(#[source] DbErr),
+
+        #[error("Journal database operation failed")]
+        #[diagnostic(code(neuronek::database))]
+        Database(#[source] DbErr),
+    }
+
+    impl Error {
+        pub(crate) fn io(context: impl Into<String>) -> impl FnOnce(std::io::Error) -> Error {
+            let context = context.into();
+            move |source| Error::Io { context, source }
+        }
+
+        /// Exit code of process which failed with this error, codes follow `sysexits.h`.
+        pub(crate) fn exit_code(&self) -> u8 {
+            match self {
+                Error::InvalidOperation(_) => 64,
+                Error::RecordNotFound { .. }
+                | Error::UnknownSubstance(_)
+                | Error::InvalidDose(_)
+                | Error::LimitExceeded(_)
+                | Error::InvalidInput(_) => 65,
+                Error::Database(_) | Error::MigrationFailed(_) | Error::Serialization(_) => 70,
+                Error::Io { .. } => 74,
+                Error::DatabaseLocked(_) => 75,
+                Error::Encryption(_) => 77,
+                Error::Configuration(_) => 78,
+            }
+        }
+    }
+
+    impl From<DbErr> for Error {
+        fn from(err: DbErr) -> Self {
+            match err.to_string().contains("database is locked") {
+                true => Error::DatabaseLocked(err),
+                false => Error::Database(err),
+            }
+        }
+    }
+
+    pub(crate) type Result<T, E = Error> = std::result::Result<T, E>;
+}
+
 mod db {
-    use std::fs::{self, File};
+    use std::collections::HashSet;
+    use std::fs::{self, File, OpenOptions};
+    use std::io::Read;
+    use std::path::{Path, PathBuf};
 
+    use age::secrecy::{ExposeSecret, SecretString};
+    use chrono::Local;
     use platform_dirs::AppDirs;
-    use sea_orm::{Database, DatabaseConnection};
+    use sea_orm::{
+        sea_query::{Alias, Expr, Func, SimpleExpr},
+        ConnectionTrait, DatabaseConnection, DbErr, EntityTrait, QuerySelect,
+        SqlxSqliteConnector, Statement,
+    };
     use sea_orm_migration::prelude::*;
+    use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
 
     use sea_migration::Migrator;
+    use ulid::Ulid;
+
+    use crate::error::{Error, Result};
+    use crate::{config, crypto};
+
+    /// Number of automatic backups created before migrations which are kept.
+    const MIGRATION_BACKUPS_KEPT: usize = 5;
 
-    fn get_database_uri() -> String {
-        let application_directories = AppDirs::new(Some("xyz.neuronek.cli"), true).unwrap();
+    fn get_application_directories() -> Result<AppDirs> {
+        AppDirs::new(Some("xyz.neuronek.cli"), true).ok_or_else(|| {
+            Error::Configuration("Home directory could not be determined".to_owned())
+        })
+    }
+
+    /// Name of profile which journal is stored directly in data directory.
+    pub(crate) const DEFAULT_PROFILE: &str = "default";
+
+    /// Directory with journals of all profiles.
+    pub(crate) fn get_data_directory() -> Result<PathBuf> {
+        match config::get_portable_directory() {
+            Some(directory) => Ok(directory.join("data")),
+            None => Ok(get_application_directories()?.data_dir),
+        }
+    }
+
+    /// Journal database of profile, every profile other than default one
+    /// has its own directory so backups of profiles are kept apart.
+    pub(crate) fn get_profile_database_path(profile: &str) -> Result<PathBuf> {
+        match profile {
+            DEFAULT_PROFILE => Ok(get_data_directory()?.join("data.db")),
+            profile => Ok(get_data_directory()?.join("profiles").join(profile).join("data.db")),
+        }
+    }
+
+    /// Profile which journal is used, there is none when database was chosen explicitly.
+    pub(crate) fn get_active_profile() -> Option<&'static str> {
+        let configuration = config::get();
+
+        match configuration.database_path {
+            Some(_) => None,
+            None => Some(configuration.profile.as_deref().unwrap_or(DEFAULT_PROFILE)),
+        }
+    }
+
+    pub(crate) fn get_database_path() -> Result<PathBuf> {
+        match (&config::get().database_path, get_active_profile()) {
+            (Some(database_path), _) => Ok(database_path.clone()),
+            (None, profile) => get_profile_database_path(profile.unwrap_or(DEFAULT_PROFILE)),
+        }
+    }
+
+    /// Describes how location of journal database was chosen.
+    pub(crate) fn get_database_path_source() -> String {
+        match (&config::get().database_path, get_active_profile()) {
+            (Some(_), _) => config::get_source("database_path"),
+            (None, Some(profile)) if profile != DEFAULT_PROFILE => {
+                format!("profile {} from {}", profile, config::get_source("profile"))
+            }
+            _ if config::get_portable_directory().is_some() => String::from("portable mode"),
+            _ => String::from("default"),
+        }
+    }
+
+    /// Backups are stored next to journal database they were made of.
+    pub(crate) fn get_backup_directory() -> Result<PathBuf> {
+        match get_database_path()?.parent() {
+            Some(directory) => Ok(directory.join("backups")),
+            None => Ok(get_data_directory()?.join("backups")),
+        }
+    }
 
-        dbg!(&application_directories);
+    pub(crate) fn get_database_uri_for(path: &Path) -> String {
+        format!("sqlite://{}", path.display())
+    }
 
-        let database_file_path = application_directories.data_dir.join("data.db");
-        dbg!(&database_file_path);
+    fn get_database_file() -> Result<PathBuf> {
+        let database_file_path = get_database_path()?;
+        tracing::debug!(
+            path = %database_file_path.display(),
+            source = %get_database_path_source(),
+            "Resolved journal database"
+        );
 
-        fs::create_dir_all(&application_directories.data_dir).unwrap();
+        if let Some(directory) = database_file_path.parent() {
+            fs::create_dir_all(directory)
+                .map_err(Error::io(format!("Failed to create {}", directory.display())))?;
+        }
 
         if !&database_file_path.exists() {
-            File::create(&database_file_path).unwrap();
+            File::create(&database_file_path)
+                .map_err(Error::io(format!("Failed to create {}", database_file_path.display())))?;
         }
 
-        let db_path = database_file_path.into_os_string().into_string();
-        let db_uri = "sqlite://".to_string() + &db_path.unwrap();
+        Ok(database_file_path)
+    }
+
+    /// Connects to journal database of active profile, database is created when it does not exist yet.
+    pub(crate) async fn connect_journal() -> Result<DatabaseConnection> {
+        let database_path = get_database_file()?;
+        tracing::info!("Connecting to database at {}", get_database_uri_for(&database_path));
+        open_database(&database_path, false).await
+    }
+
+    const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
 
-        dbg!(&db_uri);
+    /// Whether database file is encrypted with SQLCipher, unencrypted files
+    /// always start with SQLite header while encrypted ones look like noise.
+    pub(crate) fn is_database_encrypted(path: &Path) -> bool {
+        let mut header = [0u8; 16];
 
-        db_uri
+        File::open(path)
+            .and_then(|mut file| file.read_exact(&mut header))
+            .is_ok_and(|_| &header != SQLITE_HEADER)
     }
 
-    lazy_static::lazy_static! {
-    #[derive(Clone, Debug)]
-     pub static ref DATABASE_CONNECTION: DatabaseConnection = {
-             async_std::task::block_on(async {
-                 let db_url = get_database_uri();
-                 dbg!(&db_url);
-                 println!("Connecting to database at {:#?}", &db_url);
-                 Database::connect(db_url).await.unwrap()
-             })
-         };
-     }
+    fn quote_sql(value: &str) -> String {
+        format!("'{}'", value.replace('\'', "''"))
+    }
 
-    pub(super) async fn migrate_database(database_connection: &DatabaseConnection) {
-        let pending_migrations =
-            Migrator::get_pending_migrations(&database_connection.into_schema_manager_connection())
-                .await
-                .unwrap_or_else(|err| {
-                    println!("Failed to read pending migrations");
-                    panic!("{}", err)
-                });
+    /// Connects to database file, when passphrase is given it's used as SQLCipher key.
+    pub(crate) async fn connect_database(
+        path: &Path,
+        read_only: bool,
+        passphrase: Option<&SecretString>,
+    ) -> Result<DatabaseConnection> {
+        let mut options = SqliteConnectOptions::new()
+            .filename(path)
+            .read_only(read_only)
+            .create_if_missing(!read_only);
 
-        if !pending_migrations.is_empty() {
-            println!("There are {} migrations pending.", pending_migrations.len());
-            println!("Applying migrations...");
-            Migrator::up(database_connection.into_schema_manager_connection(), None)
+        if let Some(passphrase) = passphrase {
+            options = options.pragma("key", quote_sql(passphrase.expose_secret()));
+        }
+
+        // Single connection, same as default of `Database::connect` for SQLite.
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(options)
+            .await
+            .map_err(|err| DbErr::Conn(sea_orm::RuntimeErr::SqlxError(err)))?;
+        let database_connection = SqlxSqliteConnector::from_sqlx_sqlite_pool(pool);
+
+        // SQLCipher accepts any key and fails only once database is read.
+        if passphrase.is_some()
+            && let Err(err) = database_connection
+                .execute_unprepared("SELECT count(*) FROM sqlite_master")
                 .await
-                .unwrap();
-        } else {
-            println!("Everything is up to date!")
+        {
+            database_connection.close().await?;
+            return Err(Error::Encryption(format!(
+                "Failed to unlock {}, passphrase is probably wrong: {}",
+                path.display(),
+                err
+            )));
         }
-    }
-}
 
-mod cli {
-    use crate::db;
-    use clap::{Parser, Subcommand};
-    use std::{ops::Deref, path::PathBuf};
+        Ok(database_connection)
+    }
 
-    pub(super) mod substance {
-        use clap::{Parser, Subcommand};
-        use sea_orm::{
-            ActiveModelTrait, ActiveValue, DatabaseConnection, DbErr, EntityTrait, PaginatorTrait,
-            Set, TryIntoModel,
+    /// Opens journal database file, encrypted databases are unlocked with passphrase
+    /// read from `NEURONEK_PASSPHRASE` or prompted from user.
+    pub(crate) async fn open_database(path: &Path, read_only: bool) -> Result<DatabaseConnection> {
+        let passphrase = match is_database_encrypted(path) {
+            true => Some(crypto::read_passphrase(false)?),
+            false => None,
         };
-        use tabled::{Table, Tabled};
-        use tabled::settings::Style;
 
-        #[derive(Parser, Debug)]
-        #[command(version, about, long_about = None)]
-        pub struct CreateSubstance {
-            #[arg(short, long)]
-            pub name: String,
+        connect_database(path, read_only, passphrase.as_ref()).await
+    }
+
+    /// Moves replacement over database file and removes journal files
+    /// which belonged to the previous database.
+    pub(crate) fn replace_database_file(replacement: &Path, database_path: &Path) -> std::io::Result<()> {
+        fs::rename(replacement, database_path)?;
+
+        for suffix in ["-wal", "-shm"] {
+            let mut path = database_path.to_path_buf().into_os_string();
+            path.push(suffix);
+            let _ = fs::remove_file(path);
         }
 
-        #[derive(Parser, Debug)]
-        #[command(version, about, long_about = None)]
-        pub struct UpdateSubstance {
-            #[arg(short, long)]
-            pub id: i32,
-            #[arg(short, long)]
-            pub name: Option<String>,
+        Ok(())
+    }
+
+    /// Rewrites database into a new file with `sqlcipher_export`, empty key
+    /// of the new file means it won't be encrypted.
+    async fn export_database(
+        path: &Path,
+        passphrase: Option<&SecretString>,
+        target_passphrase: &str,
+    ) -> Result<()> {
+        let exported_path = path.with_extension("db.export");
+        let _ = fs::remove_file(&exported_path);
+
+        let database_connection = connect_database(path, false, passphrase).await?;
+        let export = async {
+            database_connection
+                .execute_unprepared(&format!(
+                    "ATTACH DATABASE {} AS exported KEY {}",
+                    quote_sql(&exported_path.to_string_lossy()),
+                    quote_sql(target_passphrase)
+                ))
+                .await?;
+            database_connection
+                .execute_unprepared("SELECT sqlcipher_export('exported')")
+                .await?;
+            database_connection
+                .execute_unprepared("DETACH DATABASE exported")
+                .await
         }
+            .await;
+        database_connection.close().await?;
 
-        #[derive(Parser, Debug)]
-        #[command(version, about, long_about = None)]
-        pub struct DeleteSubstance {
-            #[arg(short, long)]
-            pub id: String,
+        if let Err(err) = export {
+            let _ = fs::remove_file(&exported_path);
+            return Err(err.into());
         }
 
-        #[derive(Parser, Debug)]
-        #[command(version, about, long_about = None)]
-        pub struct ListSubstance {
-            #[arg(short = 'l', long, default_value_t = 10)]
-            pub limit: u64,
-            #[arg(short = 'p', long, default_value_t = 0)]
-            pub page: u64,
+        replace_database_file(&exported_path, path)
+            .map_err(Error::io(format!("Failed to replace {}", path.display())))
+    }
+
+    /// Encrypts unencrypted database file in place.
+    pub(crate) async fn encrypt_database(path: &Path, passphrase: &SecretString) -> Result<()> {
+        if is_database_encrypted(path) {
+            return Err(Error::InvalidOperation(format!("{} is already encrypted", path.display())));
         }
 
-        #[derive(Subcommand)]
-        pub enum SubstanceCommands {
-            Create(CreateSubstance),
-            Update(UpdateSubstance),
-            Delete(DeleteSubstance),
-            List(ListSubstance),
+        export_database(path, None, passphrase.expose_secret()).await
+    }
+
+    /// Decrypts encrypted database file in place.
+    pub(crate) async fn decrypt_database(path: &Path, passphrase: &SecretString) -> Result<()> {
+        if !is_database_encrypted(path) {
+            return Err(Error::InvalidOperation(format!("{} is not encrypted", path.display())));
         }
 
-        #[derive(Parser)]
-        #[command(args_conflicts_with_subcommands = true)]
-        pub(crate) struct SubstanceCommand {
-            #[command(subcommand)]
-            pub command: SubstanceCommands,
+        export_database(path, Some(passphrase), "").await
+    }
+
+    /// Temporary file in data directory readable only by its owner, it's removed
+    /// when dropped so copies of journal, which may be unencrypted, are not left behind on errors.
+    pub(crate) struct PrivateFile {
+        path: PathBuf,
+    }
+
+    impl PrivateFile {
+        pub(crate) fn create(prefix: &str) -> Result<Self> {
+            let directory = get_data_directory()?;
+            fs::create_dir_all(&directory)
+                .map_err(Error::io(format!("Failed to create {}", directory.display())))?;
+
+            let path = directory.join(format!(".{}-{}.db", prefix, Ulid::new()));
+            let mut options = OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+            options
+                .open(&path)
+                .map_err(Error::io(format!("Failed to create {}", path.display())))?;
+
+            Ok(Self { path })
         }
 
-        #[derive(Tabled)]
-        pub(crate) struct Substance {
-            id: i32,
-            name: String,
+        pub(crate) fn path(&self) -> &Path {
+            &self.path
         }
+    }
 
-        pub async fn create_substance(
-            create_substance_command: CreateSubstance,
-            db_conn: &DatabaseConnection,
-        ) -> Result<sea_entity::substance::Model, DbErr> {
-            let substance_active_model = sea_entity::substance::ActiveModel {
-                name: ActiveValue::set(create_substance_command.name),
-                ..Default::default()
-            };
-            let substance_model = substance_active_model.insert(db_conn).await.unwrap();
-            substance_model.try_into_model()
+    impl Drop for PrivateFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.path);
+
+            for suffix in ["-journal", "-wal", "-shm"] {
+                let mut path = self.path.clone().into_os_string();
+                path.push(suffix);
+                let _ = fs::remove_file(path);
+            }
         }
+    }
 
-        pub async fn update_substance(
-            update_substance: UpdateSubstance,
-            db_conn: &DatabaseConnection,
-        ) -> Result<sea_entity::substance::Model, DbErr> {
-            let active_model = sea_entity::substance::ActiveModel {
-                id: Set(update_substance.id),
-                name: update_substance
-                    .name
-                    .map(ActiveValue::set)
-                    .unwrap_or(ActiveValue::not_set()),
-                // ..Default::default()
-            };
-
-            active_model.update(db_conn).await.map_err(|err| {
-                println!("{}", err);
-                err
+    /// Writes consistent snapshot of database into a new file with `VACUUM INTO`,
+    /// which in contrary to copying the file is safe while database is in use.
+    pub(crate) async fn backup_database(
+        database_connection: &impl ConnectionTrait,
+        target: &Path,
+    ) -> Result<()> {
+        if target.exists() {
+            return Err(Error::InvalidOperation(format!(
+                "Backup file {} already exists",
+                target.display()
+            )));
+        }
+
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(Error::io(format!("Failed to create {}", parent.display())))?;
+        }
+
+        vacuum_into(database_connection, target).await
+    }
+
+    /// Writes snapshot of database into a private temporary file, `VACUUM INTO` keeps
+    /// SQLCipher encryption so snapshot of encrypted journal is encrypted with its key.
+    pub(crate) async fn snapshot_database(database_connection: &impl ConnectionTrait) -> Result<PrivateFile> {
+        let snapshot = PrivateFile::create("snapshot")?;
+        vacuum_into(database_connection, snapshot.path()).await?;
+        Ok(snapshot)
+    }
+
+    /// Target of `VACUUM INTO` has to be missing or empty.
+    async fn vacuum_into(database_connection: &impl ConnectionTrait, target: &Path) -> Result<()> {
+        database_connection
+            .execute(Statement::from_sql_and_values(
+                database_connection.get_database_backend(),
+                "VACUUM INTO ?",
+                [target.to_string_lossy().to_string().into()],
+            ))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Path of a new timestamped backup, name of file starts with given prefix.
+    pub(crate) fn timestamped_backup_path(prefix: &str) -> Result<PathBuf> {
+        Ok(get_backup_directory()?.join(format!(
+            "{}-{}.db",
+            prefix,
+            Local::now().format("%Y%m%d-%H%M%S%3f")
+        )))
+    }
+
+    /// Removes the oldest backups with given prefix so only `keep` of them remain.
+    pub(crate) fn rotate_backups(directory: &Path, prefix: &str, keep: usize) -> std::io::Result<()> {
+        if !directory.exists() {
+            return Ok(());
+        }
+
+        let mut backups: Vec<PathBuf> = fs::read_dir(directory)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with(&format!("{}-", prefix)) && name.ends_with(".db"))
             })
+            .collect();
+
+        // Timestamps in names are sortable, so the oldest backups come first.
+        backups.sort();
+
+        let excess = backups.len().saturating_sub(keep);
+        for backup in backups.into_iter().take(excess) {
+            fs::remove_file(backup)?;
         }
 
-        pub async fn list_substances(
-            list_substance_query: ListSubstance,
-            database_connection: &DatabaseConnection,
-        ) {
-            let entities = sea_entity::substance::Entity::find()
-                .paginate(database_connection, list_substance_query.limit)
-                .fetch_page(list_substance_query.page)
+        Ok(())
+    }
+
+    /// Checks whether database file is a journal which can be used by this version
+    /// of application and returns number of migrations it's missing.
+    pub(crate) async fn validate_journal_database(
+        database_connection: &DatabaseConnection,
+    ) -> Result<usize> {
+        let integrity = database_connection
+            .query_one(Statement::from_string(
+                database_connection.get_database_backend(),
+                "PRAGMA integrity_check",
+            ))
+            .await?
+            .map(|row| row.try_get_by_index::<String>(0))
+            .transpose()?;
+
+        if integrity.as_deref() != Some("ok") {
+            return Err(Error::InvalidInput(format!(
+                "Database file is corrupted: {}",
+                integrity.unwrap_or_default()
+            )));
+        }
+
+        let applied_migrations: Vec<String> = database_connection
+            .query_all(Statement::from_string(
+                database_connection.get_database_backend(),
+                "SELECT version FROM seaql_migrations",
+            ))
+            .await
+            .map_err(|_| Error::InvalidInput("Database file is not a journal".to_owned()))?
+            .into_iter()
+            .map(|row| row.try_get_by_index::<String>(0))
+            .collect::<Result<_, _>>()?;
+
+        let known_migrations: HashSet<String> = Migrator::migrations()
+            .iter()
+            .map(|migration| migration.name().to_owned())
+            .collect();
+
+        let unknown_migrations: Vec<&String> = applied_migrations
+            .iter()
+            .filter(|migration| !known_migrations.contains(*migration))
+            .collect();
+
+        if !unknown_migrations.is_empty() {
+            return Err(Error::InvalidInput(format!(
+                "Database was created by newer version of application, unknown migrations: {:?}",
+                unknown_migrations
+            )));
+        }
+
+        Ok(known_migrations.len() - applied_migrations.len())
+    }
+
+    pub(super) async fn migrate_database(database_connection: &DatabaseConnection) -> Result<()> {
+        let pending_migrations =
+            Migrator::get_pending_migrations(&database_connection.into_schema_manager_connection())
                 .await
-                .expect("Substances should be fetched");
+                .map_err(Error::MigrationFailed)?;
 
-            let substances: Vec<Substance> = entities.into_iter().map(|entity| Substance {
-                id: entity.id,
-                name: entity.name,
-            }).collect();
+        let applied_migrations =
+            Migrator::get_applied_migrations(&database_connection.into_schema_manager_connection())
+                .await
+                .unwrap_or_default();
 
-            let mut substance_table = Table::new(substances);
-            substance_table.with(Style::rounded());
+        // There is nothing worth saving in a database which was not migrated yet.
+        if !pending_migrations.is_empty() && !applied_migrations.is_empty() {
+            let backup_path = timestamped_backup_path("pre-migration")?;
+            backup_database(database_connection, &backup_path).await?;
+            tracing::info!("Database backed up to {}", backup_path.display());
 
-            println!("{}", substance_table.to_string());
+            rotate_backups(&get_backup_directory()?, "pre-migration", MIGRATION_BACKUPS_KEPT)
+                .unwrap_or_else(|err| tracing::warn!("Failed to remove old backups: {}", err));
         }
 
-        pub async fn execute_substance_command(
-            command: SubstanceCommands,
-            database_connection: &DatabaseConnection,
-        ) {
-            match command {
-                SubstanceCommands::Create(payload) => {
-                    create_substance(payload, database_connection)
-                        .await
-                        .expect("Should create substance");
-                }
-                SubstanceCommands::Update(command) => {
-                    update_substance(command, database_connection)
-                        .await
-                        .expect("Substance should be updated");
-                }
-                SubstanceCommands::Delete(_) => todo!(),
-                SubstanceCommands::List(query) => {
-                    let substances = list_substances(query, database_connection).await;
-                }
-            }
+        if !pending_migrations.is_empty() {
+            tracing::info!("There are {} migrations pending, applying them", pending_migrations.len());
+            Migrator::up(database_connection.into_schema_manager_connection(), None)
+                .await
+                .map_err(Error::MigrationFailed)?;
+        } else {
+            tracing::debug!("Database is up to date");
         }
+
+        Ok(())
     }
-    pub(super) mod ingestion {
-        use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
-        use clap::{Parser, Subcommand};
-        use sea_orm::{ActiveModelTrait, ActiveValue, DatabaseConnection, DbErr, TryIntoModel};
 
-        fn parse_humanized_date(s: &str) -> Result<DateTime<Local>, String> {
-            fn convert_to_local(naive_dt: NaiveDateTime) -> DateTime<Local> {
-                Local.from_local_datetime(&naive_dt).unwrap()
-            }
+    /// Timestamps are stored as text with offset of the time zone they were recorded in,
+    /// comparing them as text would be incorrect so they're compared as julian day numbers.
+    pub(crate) fn julian_day(expression: impl Into<SimpleExpr>) -> SimpleExpr {
+        Func::cust(Alias::new("julianday")).arg(expression).into()
+    }
+
+    /// Short alias for next row of given entity, aliases are local to a single
+    /// database and exist only to make referencing rows from command line easier
+    /// than typing whole identifier.
+    pub(crate) async fn next_alias<E: EntityTrait>(
+        alias_column: E::Column,
+        database_connection: &impl ConnectionTrait,
+    ) -> Result<i32, DbErr> {
+        let last_alias: Option<i32> = E::find()
+            .select_only()
+            .column_as(Expr::col(alias_column).max(), "alias")
+            .into_tuple::<Option<i32>>()
+            .one(database_connection)
+            .await?
+            .flatten();
+
+        Ok(last_alias.unwrap_or(0) + 1)
+    }
+}
+
+mod crypto {
+    use age::secrecy::SecretString;
+
+    use crate::error::{Error, Result};
+
+    /// Environment variable with passphrase which is used instead of prompting for one.
+    pub(crate) const PASSPHRASE_VARIABLE: &str = "NEURONEK_PASSPHRASE";
+
+    const AGE_HEADER: &[u8] = b"age-encryption.org/v1";
+    const AGE_ARMORED_HEADER: &[u8] = b"-----BEGIN AGE ENCRYPTED FILE-----";
+
+    /// Whether content is an age file, either binary or armored one.
+    pub(crate) fn is_encrypted(content: &[u8]) -> bool {
+        content.starts_with(AGE_HEADER) || content.trim_ascii_start().starts_with(AGE_ARMORED_HEADER)
+    }
 
-            fuzzydate::parse(s)
-                .map(convert_to_local)
-                .map_err(|parse_error| format!("Failed to parse: {}", parse_error))
+    /// Reads passphrase from environment or prompts for it, new passphrases
+    /// have to be confirmed by typing them twice.
+    pub(crate) fn read_passphrase(confirm: bool) -> Result<SecretString> {
+        if let Ok(passphrase) = std::env::var(PASSPHRASE_VARIABLE) {
+            return Ok(SecretString::from(passphrase));
         }
 
-        #[derive(Parser, Debug)]
-        #[command(version, about, long_about = None)]
-        pub struct CreateIngestion {
-            #[arg(short = 's', long)]
-            pub substance_id: i32,
-            #[arg(short = 'u', long, default_value_t=String::from("mg"))]
-            pub dosage_unit: String,
-            #[arg(short = 'v', long)]
-            pub dosage_amount: f64,
-            /// Date of ingestion, by default
-            /// current date is used if not provided.
-            ///
-            /// Date can be provided as timestamp and in human-readable format such as
-            /// "today 10:00", "yesterday 13:00", "monday 15:34" which will be later
-            /// parsed into proper timestamp.
-            #[arg(
-                short='t',
-                long,
-                value_parser=parse_humanized_date,
-                default_value_t=Local::now(),
-                default_value="now"
-            )]
-            pub ingestion_date: DateTime<Local>,
+        let passphrase =
+            rpassword::prompt_password("Passphrase: ").map_err(Error::io("Failed to read passphrase"))?;
+
+        if passphrase.is_empty() {
+            return Err(Error::Encryption("Passphrase cannot be empty".to_owned()));
         }
 
-        #[derive(Subcommand)]
-        pub(crate) enum IngestionCommands {
-            Create(CreateIngestion),
-        }
+        if confirm
+            && rpassword::prompt_password("Confirm passphrase: ")
+                .map_err(Error::io("Failed to read passphrase"))?
+                != passphrase
+        {
+            return Err(Error::Encryption("Passphrases do not match".to_owned()));
+        }
+
+        Ok(SecretString::from(passphrase))
+    }
+
+    /// Encrypts content with passphrase in age format, armored output is meant for terminals.
+    pub(crate) fn encrypt(
+        plaintext: &[u8],
+        passphrase: &SecretString,
+        armor: bool,
+    ) -> Result<Vec<u8>> {
+        let recipient = age::scrypt::Recipient::new(passphrase.clone());
+
+        let ciphertext = if armor {
+            age::encrypt_and_armor(&recipient, plaintext).map(String::into_bytes)
+        } else {
+            age::encrypt(&recipient, plaintext)
+        };
+
+        ciphertext.map_err(|err| Error::Encryption(format!("Failed to encrypt: {}", err)))
+    }
+
+    pub(crate) fn decrypt(ciphertext: &[u8], passphrase: &SecretString) -> Result<Vec<u8>> {
+        let identity = age::scrypt::Identity::new(passphrase.clone());
+
+        age::decrypt(&identity, ciphertext)
+            .map_err(|err| Error::Encryption(format!("Failed to decrypt: {}", err)))
+    }
+}
+
+mod config {
+    use std::path::{Path, PathBuf};
+    use std::sync::OnceLock;
+
+    use figment::providers::{Env, Format, Serialized, Toml};
+    use figment::{Figment, Source};
+    use platform_dirs::AppDirs;
+    use serde::{Deserialize, Serialize};
+    use tabled::Tabled;
+
+    use crate::error::{Error, Result};
+    use crate::output::{self, OutputFormat};
+
+    /// Prefix of environment variables overriding configuration, nested keys
+    /// are separated with double underscore, ex. `NEURONEK_WARNINGS__LIMIT_RATIO`.
+    const ENVIRONMENT_PREFIX: &str = "NEURONEK_";
+
+    /// Database URL shared with `sea-orm-cli` and migration tooling.
+    const DATABASE_URL_VARIABLE: &str = "DATABASE_URL";
+
+    /// File next to executable which turns on portable mode, in portable mode
+    /// configuration and journal are stored next to executable as well.
+    const PORTABLE_MARKER: &str = "portable";
+
+    static CONFIGURATION: OnceLock<Configuration> = OnceLock::new();
+    static FIGMENT: OnceLock<Figment> = OnceLock::new();
+
+    /// Values given as command line arguments, these override every other source.
+    #[derive(Serialize, Default)]
+    pub(crate) struct Arguments<'a> {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub database_path: Option<&'a Path>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub profile: Option<&'a str>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub output_format: Option<OutputFormat>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub(crate) struct WarningThresholds {
+        /// Fraction of dosage limit at which ingestion is reported as approaching it
+        pub limit_ratio: f64,
+    }
+
+    impl Default for WarningThresholds {
+        fn default() -> Self {
+            WarningThresholds { limit_ratio: 0.8 }
+        }
+    }
+
+    /// What happens when new ingestion would exceed limit of substance.
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub(crate) enum LimitPolicy {
+        /// Ingestion is saved and warning is printed
+        #[default]
+        Warn,
+        /// Ingestion is not saved unless `--force` is given
+        Refuse,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub(crate) struct Configuration {
+        /// Journal database file, by default it's stored in application data directory
+        pub database_path: Option<PathBuf>,
+        /// Unit used for new ingestions when none was provided
+        pub dosage_unit: String,
+        /// Route of administration used for new ingestions when none was provided
+        pub route_of_administration: Option<String>,
+        /// Profile which journal is used, each profile has its own database
+        pub profile: Option<String>,
+        /// Time zone of imported times which do not carry one, local time zone is used if not set
+        pub timezone: Option<String>,
+        /// Format in which results are printed, ex. "table", "json", "csv"
+        pub output_format: OutputFormat,
+        /// Write logs to daily rotated files in `logs` directory next to journal data
+        pub log_file: bool,
+        /// Whether ingestions exceeding limits are only reported or refused
+        pub limit_policy: LimitPolicy,
+        pub warnings: WarningThresholds,
+    }
+
+    impl Default for Configuration {
+        fn default() -> Self {
+            Configuration {
+                database_path: None,
+                dosage_unit: String::from("mg"),
+                route_of_administration: None,
+                profile: None,
+                timezone: None,
+                output_format: OutputFormat::default(),
+                log_file: false,
+                limit_policy: LimitPolicy::default(),
+                warnings: WarningThresholds::default(),
+            }
+        }
+    }
+
+    /// Directory of executable when application runs in portable mode.
+    pub(crate) fn get_portable_directory() -> Option<PathBuf> {
+        let executable = std::env::current_exe().ok()?;
+        let directory = executable.parent()?;
+
+        directory
+            .join(PORTABLE_MARKER)
+            .exists()
+            .then(|| directory.to_path_buf())
+    }
+
+    /// User configuration file, there is none when home directory is unknown.
+    pub(crate) fn get_config_path() -> Option<PathBuf> {
+        match get_portable_directory() {
+            Some(directory) => Some(directory.join("config.toml")),
+            None => AppDirs::new(Some("neuronek"), true)
+                .map(|directories| directories.config_dir.join("config.toml")),
+        }
+    }
+
+    /// Turns `sqlite://` URL into path of database file, plain paths are left as they are.
+    fn parse_database_location(location: &Path) -> Result<PathBuf> {
+        let Some(url) = location.to_str().and_then(|location| location.strip_prefix("sqlite:")) else {
+            return Ok(location.to_path_buf());
+        };
+
+        let path = url.strip_prefix("//").unwrap_or(url);
+        let path = path.split_once('?').map_or(path, |(path, _)| path);
+
+        if path.is_empty() || path == ":memory:" {
+            return Err(Error::Configuration(format!(
+                "{} is not a database file, journal cannot be stored in memory",
+                location.display()
+            )));
+        }
+
+        Ok(PathBuf::from(path))
+    }
+
+    /// Layers configuration sources, every next one overrides previous: defaults,
+    /// user configuration file, file given with `--config`, environment, `DATABASE_URL`
+    /// and `--database` argument.
+    pub(crate) fn figment(config_file: Option<&Path>, arguments: Arguments) -> Result<Figment> {
+        let mut figment = Figment::from(Serialized::defaults(Configuration::default()));
+
+        if let Some(config_path) = get_config_path() {
+            figment = figment.merge(Toml::file(config_path));
+        }
+
+        if let Some(config_file) = config_file {
+            if !config_file.is_file() {
+                return Err(Error::Configuration(format!(
+                    "Configuration file {} does not exist",
+                    config_file.display()
+                )));
+            }
+            figment = figment.merge(Toml::file_exact(config_file));
+        }
+
+        Ok(figment
+            .merge(
+                Env::prefixed(ENVIRONMENT_PREFIX)
+                    .split("__")
+                    .ignore(&["passphrase"]),
+            )
+            .merge(
+                Env::raw()
+                    .only(&[DATABASE_URL_VARIABLE])
+                    .map(|_| "database_path".into()),
+            )
+            .merge(Serialized::defaults(arguments)))
+    }
+
+    fn extract(figment: &Figment) -> Result<Configuration> {
+        let mut configuration: Configuration = figment
+            .extract()
+            .map_err(|err| Error::Configuration(format!("Invalid configuration: {}", err)))?;
+
+        if let Some(database_path) = &configuration.database_path {
+            configuration.database_path = Some(parse_database_location(database_path)?);
+        }
+
+        if let Some(profile) = &configuration.profile {
+            crate::cli::profile::validate_profile_name(profile)?;
+        }
+
+        Ok(configuration)
+    }
+
+    /// Loads configuration which is later available through [`get`].
+    pub(crate) fn initialize(
+        config_file: Option<&Path>,
+        arguments: Arguments,
+    ) -> Result<&'static Configuration> {
+        let figment = figment(config_file, arguments)?;
+        let configuration = extract(&figment)?;
+        FIGMENT.get_or_init(|| figment);
+        Ok(CONFIGURATION.get_or_init(|| configuration))
+    }
+
+    /// Effective configuration, defaults are used when it was not initialized.
+    pub(crate) fn get() -> &'static Configuration {
+        CONFIGURATION.get_or_init(Configuration::default)
+    }
+
+    #[derive(Tabled, Serialize)]
+    pub(crate) struct ConfigurationValue {
+        pub key: String,
+        pub value: String,
+        pub source: String,
+    }
+
+    fn flatten(prefix: &str, value: serde_json::Value, values: &mut Vec<(String, serde_json::Value)>) {
+        match value {
+            serde_json::Value::Object(map) => {
+                for (key, value) in map {
+                    let key = match prefix.is_empty() {
+                        true => key,
+                        false => format!("{}.{}", prefix, key),
+                    };
+                    flatten(&key, value, values);
+                }
+            }
+            value => values.push((prefix.to_owned(), value)),
+        }
+    }
+
+    fn describe_source(figment: &Figment, key: &str) -> String {
+        let Some(metadata) = figment.find_metadata(key) else {
+            return String::from("default");
+        };
+
+        match &metadata.source {
+            Some(Source::File(path)) => format!("file {}", path.display()),
+            _ if metadata.name.contains(ENVIRONMENT_PREFIX) => format!(
+                "environment variable {}{}",
+                ENVIRONMENT_PREFIX,
+                key.replace('.', "__").to_uppercase()
+            ),
+            _ if metadata.name.contains("environment") => {
+                format!("environment variable {}", DATABASE_URL_VARIABLE)
+            }
+            _ if metadata.name == std::any::type_name::<Arguments>() => {
+                String::from("command line argument")
+            }
+            _ => String::from("default"),
+        }
+    }
+
+    /// Where value of configuration key loaded with [`initialize`] comes from.
+    pub(crate) fn get_source(key: &str) -> String {
+        match FIGMENT.get() {
+            Some(figment) => describe_source(figment, key),
+            None => String::from("default"),
+        }
+    }
+
+    /// Effective value of every configuration key with a source it came from.
+    pub(crate) fn describe(figment: &Figment) -> Result<Vec<ConfigurationValue>> {
+        let configuration = serde_json::to_value(extract(figment)?)
+            .map_err(|err| Error::Configuration(format!("Invalid configuration: {}", err)))?;
+
+        let mut values = Vec::new();
+        flatten("", configuration, &mut values);
+
+        Ok(values
+            .into_iter()
+            .map(|(key, value)| {
+                let source = describe_source(figment, &key);
+
+                let value = match value {
+                    serde_json::Value::Null => String::from("-"),
+                    serde_json::Value::String(value) => value,
+                    value => value.to_string(),
+                };
+
+                ConfigurationValue { key, value, source }
+            })
+            .collect())
+    }
+
+    pub(crate) fn show_configuration(config_file: Option<&Path>, arguments: Arguments) -> Result<()> {
+        let figment = figment(config_file, arguments)?;
+        let values = describe(&figment)?;
+        println!("{}", output::render(&values, extract(&figment)?.output_format)?);
+        Ok(())
+    }
+}
+
+mod logging {
+    use tracing_appender::rolling::{RollingFileAppender, Rotation};
+    use tracing_subscriber::filter::EnvFilter;
+    use tracing_subscriber::fmt;
+    use tracing_subscriber::prelude::*;
+
+    use crate::db;
+    use crate::error::{Error, Result};
+
+    /// Number of daily log files kept, older ones are removed on rotation.
+    const LOG_FILES_KEPT: usize = 7;
+
+    /// Filter for number of `--debug` flags, SQL statements are logged only
+    /// with the highest level, `RUST_LOG` overrides it when set.
+    pub(crate) fn filter_directives(verbosity: u8) -> &'static str {
+        match verbosity {
+            0 => "neuronek_cli=warn",
+            1 => "neuronek_cli=info",
+            2 => "neuronek_cli=debug",
+            _ => "neuronek_cli=trace,sea_orm=debug,sqlx::query=debug",
+        }
+    }
+
+    fn filter(verbosity: u8) -> EnvFilter {
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(filter_directives(verbosity)))
+    }
+
+    /// Logs go to standard error so they never mix with results, when `log_file`
+    /// is enabled they are written to rotated files as well, at least at info level.
+    pub(crate) fn initialize(verbosity: u8, log_file: bool) -> Result<()> {
+        let console = fmt::layer()
+            .with_writer(std::io::stderr)
+            .without_time()
+            .with_target(verbosity > 2)
+            .with_filter(filter(verbosity));
+
+        let file = match log_file {
+            true => {
+                let directory = db::get_data_directory()?.join("logs");
+                let appender = RollingFileAppender::builder()
+                    .rotation(Rotation::DAILY)
+                    .filename_prefix("neuronek")
+                    .filename_suffix("log")
+                    .max_log_files(LOG_FILES_KEPT)
+                    .build(&directory)
+                    .map_err(|err| {
+                        Error::Configuration(format!(
+                            "Failed to open log file in {}: {}",
+                            directory.display(),
+                            err
+                        ))
+                    })?;
+
+                Some(
+                    fmt::layer()
+                        .with_ansi(false)
+                        .with_writer(appender)
+                        .with_filter(filter(verbosity.max(1))),
+                )
+            }
+            false => None,
+        };
+
+        tracing_subscriber::registry()
+            .with(console)
+            .with(file)
+            .try_init()
+            .map_err(|err| Error::Configuration(format!("Failed to initialize logging: {}", err)))
+    }
+}
+
+mod output {
+    use std::fmt::Display;
+
+    use clap::ValueEnum;
+    use serde::{Deserialize, Serialize};
+    use tabled::settings::Style;
+    use tabled::{Table, Tabled};
+
+    use crate::config;
+    use crate::error::{Error, Result};
+
+    /// Format in which results of commands are printed.
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub(crate) enum OutputFormat {
+        /// Table with rounded borders
+        #[default]
+        Table,
+        /// JSON array, single records are written as an object
+        Json,
+        /// JSON object per line
+        Ndjson,
+        /// CSV with header row
+        Csv,
+        /// YAML sequence, single records are written as a mapping
+        Yaml,
+        /// Tab-separated values without header
+        Plain,
+    }
+
+    /// Format selected with `--output` or configuration.
+    pub(crate) fn format() -> OutputFormat {
+        config::get().output_format
+    }
+
+    /// Renders missing values of table columns.
+    pub(crate) fn display_option<T: Display>(value: &Option<T>) -> String {
+        match value {
+            Some(value) => value.to_string(),
+            None => String::from("-"),
+        }
+    }
+
+    fn to_serialization_error(err: impl Display) -> Error {
+        Error::Serialization(format!("Failed to render output: {}", err))
+    }
+
+    fn render_csv<T: Serialize>(records: &[T]) -> Result<String> {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        for record in records {
+            writer.serialize(record).map_err(to_serialization_error)?;
+        }
+
+        let content = writer.into_inner().map_err(to_serialization_error)?;
+        String::from_utf8(content).map_err(to_serialization_error)
+    }
+
+    /// Renders list of records, nothing is rendered for an empty list except for
+    /// formats which are able to represent it (`[]` in JSON and YAML).
+    pub(crate) fn render<T: Serialize + Tabled>(records: &[T], format: OutputFormat) -> Result<String> {
+        let content = match format {
+            OutputFormat::Table => Table::new(records).with(Style::rounded()).to_string(),
+            OutputFormat::Json => serde_json::to_string_pretty(records).map_err(to_serialization_error)?,
+            OutputFormat::Ndjson => records
+                .iter()
+                .map(|record| serde_json::to_string(record).map_err(to_serialization_error))
+                .collect::<Result<Vec<_>>>()?
+                .join("\n"),
+            OutputFormat::Csv => render_csv(records)?.trim_end().to_owned(),
+            OutputFormat::Yaml => serde_yaml::to_string(records)
+                .map_err(to_serialization_error)?
+                .trim_end()
+                .to_owned(),
+            OutputFormat::Plain => records
+                .iter()
+                .map(|record| record.fields().join("\t"))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        };
+
+        Ok(content)
+    }
+
+    /// Renders single record, such as the one which was just created or updated.
+    pub(crate) fn render_record<T: Serialize + Tabled>(record: &T, format: OutputFormat) -> Result<String> {
+        match format {
+            OutputFormat::Json => serde_json::to_string_pretty(record).map_err(to_serialization_error),
+            OutputFormat::Yaml => serde_yaml::to_string(record)
+                .map(|content| content.trim_end().to_owned())
+                .map_err(to_serialization_error),
+            format => render(std::slice::from_ref(record), format),
+        }
+    }
+
+    pub(crate) fn print_records<T: Serialize + Tabled>(records: &[T]) -> Result<()> {
+        let content = render(records, format())?;
+        if !content.is_empty() {
+            println!("{}", content);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn print_record<T: Serialize + Tabled>(record: &T) -> Result<()> {
+        println!("{}", render_record(record, format())?);
+        Ok(())
+    }
+
+    /// Prints note meant for humans, with formats other than table it goes
+    /// to standard error so standard output contains only records.
+    pub(crate) fn print_message(message: impl Display) {
+        match format() {
+            OutputFormat::Table => println!("{}", message),
+            _ => eprintln!("{}", message),
+        }
+    }
+
+    /// Context of the whole run such as active profile, it's always written to standard
+    /// error so data streamed to standard output (ex. exports) stays intact.
+    pub(crate) fn print_notice(message: impl Display) {
+        eprintln!("{}", message);
+    }
+}
+
+mod cli {
+    use crate::output::{self, OutputFormat};
+    use crate::{config, db, logging};
+    use clap::{Parser, Subcommand};
+    use clap_complete::engine::ArgValueCompleter;
+    use sea_orm::DatabaseConnection;
+    use std::path::PathBuf;
+
+    use crate::error::{Error, Result};
+
+    pub(super) mod substance {
+        use chrono::TimeDelta;
+        use clap::{Parser, Subcommand};
+        use clap_complete::engine::ArgValueCompleter;
+        use sea_orm::sea_query::{Expr, Func};
+        use sea_orm::{
+            ActiveModelTrait, ActiveValue, ColumnTrait, Condition, DatabaseConnection, EntityTrait,
+            PaginatorTrait, QueryFilter, QueryOrder, Set,
+        };
+        use serde::Serialize;
+        use tabled::Tabled;
+        use ulid::Ulid;
+
+        use crate::cli::completions::complete_substances;
+        use crate::cli::ingestion::{list_ingestions, IngestionFilter, ListIngestion};
+        use crate::cli::streaks::{find_streaks, format_duration, DEFAULT_MIN_STREAK};
+        use crate::error::{Error, Result};
+        use crate::output::display_option;
+        use crate::{db, output};
+
+        #[derive(Parser, Debug)]
+        #[command(version, about, long_about = None)]
+        pub struct CreateSubstance {
+            #[arg(short, long)]
+            pub name: String,
+            /// How long substance stays active, ex. `4h` or `1h30m`
+            #[arg(long, value_parser = parse_duration)]
+            pub duration: Option<TimeDelta>,
+            /// Shortest time between doses, ex. `2h`
+            #[arg(long, value_parser = parse_duration)]
+            pub redose_interval: Option<TimeDelta>,
+        }
+
+        #[derive(Parser, Debug)]
+        #[command(version, about, long_about = None)]
+        pub struct UpdateSubstance {
+            /// Alias or identifier of substance
+            #[arg(short, long, add = ArgValueCompleter::new(complete_substances))]
+            pub id: String,
+            #[arg(short, long)]
+            pub name: Option<String>,
+            /// How long substance stays active, ex. `4h` or `1h30m`
+            #[arg(long, value_parser = parse_duration)]
+            pub duration: Option<TimeDelta>,
+            /// Shortest time between doses, ex. `2h`
+            #[arg(long, value_parser = parse_duration)]
+            pub redose_interval: Option<TimeDelta>,
+        }
+
+        #[derive(Parser, Debug)]
+        #[command(version, about, long_about = None)]
+        pub struct DeleteSubstance {
+            #[arg(short, long, add = ArgValueCompleter::new(complete_substances))]
+            pub id: String,
+        }
+
+        #[derive(Parser, Debug)]
+        #[command(version, about, long_about = None)]
+        pub struct ListSubstance {
+            #[arg(short = 'l', long, default_value_t = 10)]
+            pub limit: u64,
+            #[arg(short = 'p', long, default_value_t = 0)]
+            pub page: u64,
+        }
+
+        #[derive(Parser, Debug)]
+        #[command(version, about, long_about = None)]
+        pub struct ShowSubstance {
+            /// Alias, identifier or name of substance
+            #[arg(short, long, add = ArgValueCompleter::new(complete_substances))]
+            pub id: String,
+            /// Shortest abstinence in days after which ingestion counts as relapse
+            #[arg(long, default_value_t = DEFAULT_MIN_STREAK)]
+            pub min_streak: i64,
+        }
+
+        #[derive(Subcommand)]
+        pub enum SubstanceCommands {
+            Create(CreateSubstance),
+            Update(UpdateSubstance),
+            Delete(DeleteSubstance),
+            List(ListSubstance),
+            /// Show substance with its abstinence streaks and relapses
+            Show(ShowSubstance),
+        }
+
+        #[derive(Parser)]
+        #[command(args_conflicts_with_subcommands = true)]
+        pub(crate) struct SubstanceCommand {
+            #[command(subcommand)]
+            pub command: SubstanceCommands,
+        }
+
+        #[derive(Tabled, Serialize)]
+        pub(crate) struct Substance {
+            alias: i32,
+            name: String,
+            #[tabled(display_with = "display_option")]
+            duration: Option<String>,
+            #[tabled(display_with = "display_option")]
+            redose_interval: Option<String>,
+            id: String,
+        }
+
+        impl From<sea_entity::substance::Model> for Substance {
+            fn from(model: sea_entity::substance::Model) -> Self {
+                let display_minutes = |minutes: Option<i32>| {
+                    minutes.map(|minutes| format_duration(TimeDelta::minutes(minutes.into())))
+                };
+
+                Substance {
+                    alias: model.alias,
+                    name: model.name,
+                    duration: display_minutes(model.duration_minutes),
+                    redose_interval: display_minutes(model.redose_interval_minutes),
+                    id: model.id,
+                }
+            }
+        }
+
+        /// Parses durations like `90m`, `4h`, `1h30m` or `1d`.
+        pub(crate) fn parse_duration(value: &str) -> std::result::Result<TimeDelta, String> {
+            let invalid = || format!("Invalid duration `{}`, expected ex. `45m`, `4h` or `1h30m`", value);
+            let mut minutes: i64 = 0;
+            let mut number = String::new();
+
+            for character in value.trim().chars() {
+                match character {
+                    '0'..='9' => number.push(character),
+                    'd' | 'h' | 'm' if !number.is_empty() => {
+                        let amount: i64 = number.parse().map_err(|_| invalid())?;
+                        let unit = match character {
+                            'd' => 1440,
+                            'h' => 60,
+                            _ => 1,
+                        };
+                        minutes = amount
+                            .checked_mul(unit)
+                            .and_then(|amount| minutes.checked_add(amount))
+                            .filter(|minutes| *minutes <= i64::from(i32::MAX))
+                            .ok_or_else(invalid)?;
+                        number.clear();
+                    }
+                    _ => return Err(invalid()),
+                }
+            }
+
+            match number.is_empty() && minutes > 0 {
+                true => Ok(TimeDelta::minutes(minutes)),
+                false => Err(invalid()),
+            }
+        }
+
+        fn to_minutes(duration: TimeDelta) -> i32 {
+            duration.num_minutes() as i32
+        }
+
+        /// Finds substance by its short alias (ex. `3`), full identifier or name,
+        /// names are compared case-insensitively.
+        pub async fn find_substance(
+            reference: &str,
+            db_conn: &DatabaseConnection,
+        ) -> Result<sea_entity::substance::Model> {
+            let query = match reference.parse::<i32>() {
+                Ok(alias) => sea_entity::substance::Entity::find()
+                    .filter(sea_entity::substance::Column::Alias.eq(alias)),
+                Err(_) => sea_entity::substance::Entity::find().filter(
+                    Condition::any()
+                        .add(sea_entity::substance::Column::Id.eq(reference))
+                        .add(
+                            Expr::expr(Func::lower(Expr::col(sea_entity::substance::Column::Name)))
+                                .eq(reference.trim().to_lowercase()),
+                        ),
+                ),
+            };
+
+            query
+                .one(db_conn)
+                .await?
+                .ok_or_else(|| Error::UnknownSubstance(reference.to_owned()))
+        }
+
+        pub async fn create_substance(
+            create_substance_command: CreateSubstance,
+            db_conn: &DatabaseConnection,
+        ) -> Result<sea_entity::substance::Model> {
+            let alias =
+                db::next_alias::<sea_entity::substance::Entity>(sea_entity::substance::Column::Alias, db_conn)
+                    .await?;
+
+            let substance_active_model = sea_entity::substance::ActiveModel {
+                id: ActiveValue::set(Ulid::new().to_string()),
+                alias: ActiveValue::set(alias),
+                name: ActiveValue::set(create_substance_command.name),
+                duration_minutes: ActiveValue::set(create_substance_command.duration.map(to_minutes)),
+                redose_interval_minutes: ActiveValue::set(
+                    create_substance_command.redose_interval.map(to_minutes),
+                ),
+            };
+            Ok(substance_active_model.insert(db_conn).await?)
+        }
+
+        pub async fn update_substance(
+            update_substance: UpdateSubstance,
+            db_conn: &DatabaseConnection,
+        ) -> Result<sea_entity::substance::Model> {
+            let substance = find_substance(&update_substance.id, db_conn).await?;
+
+            let mut active_model: sea_entity::substance::ActiveModel = substance.into();
+
+            if let Some(name) = update_substance.name {
+                active_model.name = Set(name);
+            }
+
+            if let Some(duration) = update_substance.duration {
+                active_model.duration_minutes = Set(Some(to_minutes(duration)));
+            }
+
+            if let Some(redose_interval) = update_substance.redose_interval {
+                active_model.redose_interval_minutes = Set(Some(to_minutes(redose_interval)));
+            }
+
+            Ok(active_model.update(db_conn).await?)
+        }
+
+        pub async fn list_substances(
+            list_substance_query: ListSubstance,
+            database_connection: &DatabaseConnection,
+        ) -> Result<()> {
+            let entities = sea_entity::substance::Entity::find()
+                .order_by_asc(sea_entity::substance::Column::Alias)
+                .paginate(database_connection, list_substance_query.limit)
+                .fetch_page(list_substance_query.page)
+                .await?;
+
+            let substances: Vec<Substance> = entities.into_iter().map(Substance::from).collect();
+
+            output::print_records(&substances)
+        }
+
+        /// Prints substance with its streaks, relapses are listed only when asked for.
+        async fn show_substance(
+            reference: &str,
+            min_streak: i64,
+            relapses: bool,
+            database_connection: &DatabaseConnection,
+        ) -> Result<sea_entity::substance::Model> {
+            let substance = find_substance(reference, database_connection).await?;
+            output::print_record(&Substance::from(substance.clone()))?;
+
+            let streaks = find_streaks(Some(substance.id.clone()), min_streak, database_connection).await?;
+            match streaks.into_iter().next() {
+                Some((summary, relapse_history)) => {
+                    output::print_record(&summary)?;
+                    if relapses {
+                        output::print_records(&relapse_history)?;
+                    }
+                }
+                None => output::print_message(format!("There are no ingestions of {} yet.", substance.name)),
+            }
+
+            Ok(substance)
+        }
+
+        /// Summary shown by `nurk <substance>`: substance, its streaks and the latest ingestions.
+        pub async fn summarize_substance(reference: &str, database_connection: &DatabaseConnection) -> Result<()> {
+            let substance = show_substance(reference, DEFAULT_MIN_STREAK, false, database_connection).await?;
+
+            let query = ListIngestion {
+                filter: IngestionFilter {
+                    substance: Some(substance.id),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            list_ingestions(query, database_connection).await
+        }
+
+        pub async fn execute_substance_command(
+            command: SubstanceCommands,
+            database_connection: &DatabaseConnection,
+        ) -> Result<()> {
+            match command {
+                SubstanceCommands::Create(payload) => {
+                    let substance = create_substance(payload, database_connection).await?;
+                    output::print_record(&Substance::from(substance))?;
+                }
+                SubstanceCommands::Update(command) => {
+                    let substance = update_substance(command, database_connection).await?;
+                    output::print_record(&Substance::from(substance))?;
+                }
+                SubstanceCommands::Delete(_) => {
+                    return Err(Error::InvalidOperation(
+                        "Deleting substances is not supported yet".to_owned(),
+                    ));
+                }
+                SubstanceCommands::List(query) => {
+                    list_substances(query, database_connection).await?;
+                }
+                SubstanceCommands::Show(query) => {
+                    show_substance(&query.id, query.min_streak, true, database_connection).await?;
+                }
+            }
+
+            Ok(())
+        }
+    }
+    pub(super) mod ingestion {
+        use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+        use clap::{Args, Parser, Subcommand};
+        use clap_complete::engine::ArgValueCompleter;
+        use serde::Serialize;
+        use sea_orm::sea_query::Expr;
+        use sea_orm::{
+            ActiveModelTrait, ActiveValue, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter,
+            QueryOrder, QuerySelect, SelectTwo,
+        };
+        use tabled::Tabled;
+        use ulid::Ulid;
+
+        use crate::cli::completions::{complete_doses, complete_substances};
+        use crate::cli::limits::{check_limits, print_warnings, LimitUsage};
+        use crate::cli::substance::find_substance;
+        use crate::error::{Error, Result};
+        use crate::output::{self, display_option};
+        use crate::{config, db};
+
+        /// Parses date without time zone, ISO-8601 dates such as "2024-08-01 13:00" are
+        /// tried first as `fuzzydate` understands only human-readable ones.
+        pub(crate) fn parse_naive_date(s: &str) -> Result<NaiveDateTime, String> {
+            const ISO_FORMATS: [&str; 4] = [
+                "%Y-%m-%d %H:%M:%S%.f",
+                "%Y-%m-%d %H:%M",
+                "%Y-%m-%dT%H:%M:%S%.f",
+                "%Y-%m-%dT%H:%M",
+            ];
+
+            let s = s.trim();
+
+            ISO_FORMATS
+                .iter()
+                .find_map(|format| NaiveDateTime::parse_from_str(s, format).ok())
+                .or_else(|| {
+                    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                        .ok()
+                        .and_then(|date| date.and_hms_opt(0, 0, 0))
+                })
+                .map(Ok)
+                .unwrap_or_else(|| {
+                    fuzzydate::parse(s)
+                        .map_err(|parse_error| format!("Failed to parse: {}", parse_error))
+                })
+        }
+
+        pub(crate) fn parse_humanized_date(s: &str) -> Result<DateTime<Local>, String> {
+            // Times skipped by daylight saving time change do not exist in local time zone.
+            fn convert_to_local(naive_dt: NaiveDateTime) -> Result<DateTime<Local>, String> {
+                Local
+                    .from_local_datetime(&naive_dt)
+                    .earliest()
+                    .ok_or_else(|| format!("{} does not exist in local time zone", naive_dt))
+            }
+
+            parse_naive_date(s).and_then(convert_to_local)
+        }
+
+        #[derive(Parser, Debug)]
+        #[command(version, about, long_about = None)]
+        pub struct CreateIngestion {
+            /// Alias or identifier of ingested substance
+            #[arg(short = 's', long, add = ArgValueCompleter::new(complete_substances))]
+            pub substance_id: String,
+            /// Unit of dosage, configured default unit is used if not provided
+            #[arg(short = 'u', long)]
+            pub dosage_unit: Option<String>,
+            /// Amount of dosage, amount and unit of the last ingestion of substance are
+            /// used if neither amount nor unit is provided
+            #[arg(short = 'v', long, add = ArgValueCompleter::new(complete_doses))]
+            pub dosage_amount: Option<f64>,
+            /// Date of ingestion, by default
+            /// current date is used if not provided.
+            ///
+            /// Date can be provided as timestamp and in human-readable format such as
+            /// "today 10:00", "yesterday 13:00", "monday 15:34" which will be later
+            /// parsed into proper timestamp.
+            #[arg(
+                short='t',
+                long,
+                value_parser=parse_humanized_date,
+                default_value_t=Local::now(),
+                default_value="now"
+            )]
+            pub ingestion_date: DateTime<Local>,
+            /// Route of administration, ex. "oral", "sublingual", "insufflated",
+            /// configured default route is used if not provided
+            #[arg(short = 'r', long = "route")]
+            pub route_of_administration: Option<String>,
+            /// Free-form notes about ingestion
+            #[arg(short = 'n', long)]
+            pub notes: Option<String>,
+            /// Save ingestion even when it exceeds limit of substance
+            #[arg(long)]
+            pub force: bool,
+        }
+
+        /// Converts mass units to milligrams so doses recorded in different units can be
+        /// added up, other units (ex. "ml", "pill") are only trimmed and lowercased.
+        pub(crate) fn normalize_dosage(amount: f64, unit: &str) -> (f64, String) {
+            let unit = unit.trim().to_lowercase();
+            let factor = match unit.as_str() {
+                "ug" | "µg" | "μg" | "mcg" => 0.001,
+                "mg" => 1.0,
+                "g" => 1_000.0,
+                "kg" => 1_000_000.0,
+                _ => return (amount, unit),
+            };
+
+            (amount * factor, String::from("mg"))
+        }
+
+        /// Filters shared by commands which are operating on a range of ingestions.
+        #[derive(Args, Debug, Clone, Default)]
+        pub struct IngestionFilter {
+            /// Only ingestions of substance with given alias, identifier or name
+            #[arg(short = 's', long, add = ArgValueCompleter::new(complete_substances))]
+            pub substance: Option<String>,
+            /// Only ingestions since given date, ex. "2024-08-01", "last monday"
+            #[arg(long, value_parser = parse_humanized_date)]
+            pub since: Option<DateTime<Local>>,
+            /// Only ingestions until given date, ex. "yesterday 23:59"
+            #[arg(long, value_parser = parse_humanized_date)]
+            pub until: Option<DateTime<Local>>,
+        }
+
+        #[derive(Parser, Debug)]
+        #[command(version, about, long_about = None)]
+        pub struct Again {
+            /// Repeat the last ingestion of this substance instead of the last ingestion at all
+            #[arg(add = ArgValueCompleter::new(complete_substances))]
+            pub substance: Option<String>,
+            /// Save ingestion even when it exceeds limit of substance
+            #[arg(long)]
+            pub force: bool,
+        }
+
+        /// Number of the latest ingestions listed when limit is not given.
+        pub(crate) const DEFAULT_LIST_LIMIT: u64 = 10;
+
+        #[derive(Parser, Debug)]
+        #[command(version, about, long_about = None)]
+        pub struct ListIngestion {
+            /// Number of the latest ingestions to show
+            #[arg(short = 'l', long, default_value_t = DEFAULT_LIST_LIMIT)]
+            pub limit: u64,
+            #[command(flatten)]
+            pub filter: IngestionFilter,
+        }
+
+        impl Default for ListIngestion {
+            fn default() -> Self {
+                ListIngestion {
+                    limit: DEFAULT_LIST_LIMIT,
+                    filter: IngestionFilter::default(),
+                }
+            }
+        }
+
+        #[derive(Subcommand)]
+        pub(crate) enum IngestionCommands {
+            Create(CreateIngestion),
+            /// Show the latest ingestions, this is also what `nurk` without command does
+            List(ListIngestion),
+        }
+
+        #[derive(Tabled, Serialize)]
+        pub(crate) struct Ingestion {
+            pub alias: i32,
+            pub substance: String,
+            pub dosage: f64,
+            pub unit: String,
+            #[tabled(display_with = "display_option")]
+            pub route: Option<String>,
+            pub ingested_at: String,
+            #[tabled(display_with = "display_option")]
+            pub notes: Option<String>,
+            pub id: String,
+        }
+
+        impl Ingestion {
+            pub(crate) fn new(
+                ingestion: sea_entity::ingestion::Model,
+                substance: sea_entity::substance::Model,
+            ) -> Self {
+                Ingestion {
+                    alias: ingestion.alias,
+                    substance: substance.name,
+                    dosage: ingestion.dosage_value,
+                    unit: ingestion.dosage_unit,
+                    route: ingestion.route_of_administration,
+                    ingested_at: ingestion.ingested_at.to_rfc3339(),
+                    notes: ingestion.notes,
+                    id: ingestion.id,
+                }
+            }
+        }
+
+        #[derive(Parser)]
+        #[command(args_conflicts_with_subcommands = true)]
+        pub(crate) struct IngestionCommand {
+            #[command(subcommand)]
+            pub command: IngestionCommands,
+        }
+
+        /// Saves ingestion and returns it together with usage of limits it approaches or exceeds.
+        pub async fn create_ingestion(
+            create_ingestion_command: CreateIngestion,
+            db_conn: &DatabaseConnection,
+        ) -> Result<(sea_entity::ingestion::Model, Vec<LimitUsage>)> {
+            if let Some(dosage_amount) = create_ingestion_command.dosage_amount
+                && (!dosage_amount.is_finite() || dosage_amount <= 0.0)
+            {
+                return Err(Error::InvalidDose(format!("{} is not a positive amount", dosage_amount)));
+            }
+
+            if create_ingestion_command.dosage_amount.is_none() && create_ingestion_command.dosage_unit.is_some() {
+                return Err(Error::InvalidDose(
+                    "Amount is required when unit is given, without both the last dose is repeated".to_owned(),
+                ));
+            }
+
+            let configuration = config::get();
+            let substance = find_substance(&create_ingestion_command.substance_id, db_conn).await?;
+
+            let (dosage_amount, dosage_unit) = match create_ingestion_command.dosage_amount {
+                Some(dosage_amount) => (
+                    dosage_amount,
+                    create_ingestion_command
+                        .dosage_unit
+                        .unwrap_or_else(|| configuration.dosage_unit.clone()),
+                ),
+                None => {
+                    let (last_ingestion, _) = find_last_ingestion(Some(&substance.id), db_conn)
+                        .await?
+                        .ok_or_else(|| {
+                            Error::InvalidDose(format!(
+                                "Amount is required as there is no previous ingestion of {}",
+                                substance.name
+                            ))
+                        })?;
+                    (last_ingestion.dosage_value, last_ingestion.dosage_unit)
+                }
+            };
+
+            let warnings = check_limits(
+                &substance,
+                dosage_amount,
+                &dosage_unit,
+                create_ingestion_command.ingestion_date,
+                create_ingestion_command.force,
+                db_conn,
+            )
+                .await?;
+
+            let alias =
+                db::next_alias::<sea_entity::ingestion::Entity>(sea_entity::ingestion::Column::Alias, db_conn)
+                    .await?;
+
+            let active_model = sea_entity::ingestion::ActiveModel {
+                id: ActiveValue::Set(Ulid::new().to_string()),
+                alias: ActiveValue::Set(alias),
+                substance_id: ActiveValue::Set(substance.id),
+                dosage_unit: ActiveValue::Set(dosage_unit),
+                dosage_value: ActiveValue::Set(dosage_amount),
+                route_of_administration: ActiveValue::Set(
+                    create_ingestion_command
+                        .route_of_administration
+                        .or_else(|| configuration.route_of_administration.clone()),
+                ),
+                notes: ActiveValue::Set(create_ingestion_command.notes),
+                ingested_at: ActiveValue::Set(create_ingestion_command.ingestion_date.into()),
+                created_at: ActiveValue::Set(Utc::now().into()),
+                updated_at: ActiveValue::Set(Utc::now().into()),
+            };
+
+            Ok((active_model.insert(db_conn).await?, warnings))
+        }
+
+        /// The most recent ingestion, optionally only of substance with given identifier.
+        pub(crate) async fn find_last_ingestion(
+            substance_id: Option<&str>,
+            db_conn: &DatabaseConnection,
+        ) -> Result<Option<(sea_entity::ingestion::Model, sea_entity::substance::Model)>> {
+            let mut query = sea_entity::ingestion::Entity::find()
+                .find_also_related(sea_entity::substance::Entity)
+                .order_by_desc(db::julian_day(Expr::col(sea_entity::ingestion::Column::IngestedAt)));
+
+            if let Some(substance_id) = substance_id {
+                query = query.filter(sea_entity::ingestion::Column::SubstanceId.eq(substance_id));
+            }
+
+            match query.one(db_conn).await? {
+                Some((ingestion, Some(substance))) => Ok(Some((ingestion, substance))),
+                Some((ingestion, None)) => Err(Error::RecordNotFound {
+                    entity: "substance",
+                    reference: ingestion.substance_id,
+                }),
+                None => Ok(None),
+            }
+        }
+
+        /// Logs the last ingestion (of given substance) again at current time, notes are not repeated.
+        pub async fn repeat_ingestion(
+            again: Again,
+            db_conn: &DatabaseConnection,
+        ) -> Result<(sea_entity::ingestion::Model, sea_entity::substance::Model, Vec<LimitUsage>)> {
+            let substance = match &again.substance {
+                Some(reference) => Some(find_substance(reference, db_conn).await?),
+                None => None,
+            };
+
+            let (last_ingestion, last_substance) =
+                find_last_ingestion(substance.as_ref().map(|substance| substance.id.as_str()), db_conn)
+                    .await?
+                    .ok_or_else(|| {
+                        Error::InvalidOperation(match &substance {
+                            Some(substance) => format!("There is no ingestion of {} to repeat", substance.name),
+                            None => "There is no ingestion to repeat".to_owned(),
+                        })
+                    })?;
+
+            let (ingestion, warnings) = create_ingestion(
+                CreateIngestion {
+                    substance_id: last_substance.id.clone(),
+                    dosage_unit: Some(last_ingestion.dosage_unit),
+                    dosage_amount: Some(last_ingestion.dosage_value),
+                    ingestion_date: Local::now(),
+                    route_of_administration: last_ingestion.route_of_administration,
+                    notes: None,
+                    force: again.force,
+                },
+                db_conn,
+            )
+                .await?;
+
+            Ok((ingestion, last_substance, warnings))
+        }
+
+        /// Time between now and given moment in the largest whole unit, ex. "5m ago"
+        /// or "in 2h" for ingestions planned ahead, moments a week or more apart are shown as dates.
+        pub(crate) fn relative_time(time: DateTime<Local>, now: DateTime<Local>) -> String {
+            let distance = (now - time).abs();
+
+            let amount = match distance.num_minutes() {
+                minutes if minutes < 1 => return String::from("just now"),
+                minutes if minutes < 60 => format!("{}m", minutes),
+                _ if distance.num_hours() < 24 => format!("{}h", distance.num_hours()),
+                _ if distance.num_days() < 7 => format!("{}d", distance.num_days()),
+                _ => return time.format("%Y-%m-%d").to_string(),
+            };
+
+            match time > now {
+                true => format!("in {}", amount),
+                false => format!("{} ago", amount),
+            }
+        }
+
+        /// One line per ingestion in `jj log` manner: alias, relative time, substance and dose.
+        pub(crate) fn format_log(
+            ingestions: &[(sea_entity::ingestion::Model, sea_entity::substance::Model)],
+            now: DateTime<Local>,
+        ) -> Vec<String> {
+            let width = ingestions
+                .iter()
+                .map(|(_, substance)| substance.name.chars().count())
+                .max()
+                .unwrap_or_default();
+
+            ingestions
+                .iter()
+                .map(|(ingestion, substance)| {
+                    let mut line = format!(
+                        "{:>4}  {:>10}  {:<width$}  {} {}",
+                        ingestion.alias,
+                        relative_time(ingestion.ingested_at.with_timezone(&Local), now),
+                        substance.name,
+                        ingestion.dosage_value,
+                        ingestion.dosage_unit,
+                    );
+                    if let Some(route) = &ingestion.route_of_administration {
+                        line.push_str(&format!(" {}", route));
+                    }
+                    if let Some(notes) = &ingestion.notes {
+                        line.push_str(&format!("  \"{}\"", notes));
+                    }
+                    line
+                })
+                .collect()
+        }
+
+        /// Prints the latest ingestions from the newest one, as compact log with table format.
+        pub async fn list_ingestions(query: ListIngestion, db_conn: &DatabaseConnection) -> Result<()> {
+            let ingestions = find_latest_ingestions(&query.filter, query.limit, db_conn).await?;
+
+            if ingestions.is_empty() {
+                output::print_message("There are no ingestions yet, log one with `ingestion create`.");
+                return Ok(());
+            }
+
+            match output::format() {
+                output::OutputFormat::Table => {
+                    for line in format_log(&ingestions, Local::now()) {
+                        println!("{}", line);
+                    }
+                    Ok(())
+                }
+                _ => output::print_records(
+                    &ingestions
+                        .into_iter()
+                        .map(|(ingestion, substance)| Ingestion::new(ingestion, substance))
+                        .collect::<Vec<_>>(),
+                ),
+            }
+        }
+
+        /// Query of ingestions matching given filter joined with their substances.
+        async fn filter_ingestions(
+            filter: &IngestionFilter,
+            db_conn: &DatabaseConnection,
+        ) -> Result<SelectTwo<sea_entity::ingestion::Entity, sea_entity::substance::Entity>> {
+            let mut query = sea_entity::ingestion::Entity::find().find_also_related(sea_entity::substance::Entity);
+
+            if let Some(reference) = &filter.substance {
+                let substance = find_substance(reference, db_conn).await?;
+                query = query.filter(sea_entity::ingestion::Column::SubstanceId.eq(substance.id));
+            }
+
+            if let Some(since) = filter.since {
+                query = query.filter(
+                    Expr::expr(db::julian_day(Expr::col(sea_entity::ingestion::Column::IngestedAt)))
+                        .gte(db::julian_day(Expr::val(since.fixed_offset()))),
+                );
+            }
+
+            if let Some(until) = filter.until {
+                query = query.filter(
+                    Expr::expr(db::julian_day(Expr::col(sea_entity::ingestion::Column::IngestedAt)))
+                        .lte(db::julian_day(Expr::val(until.fixed_offset()))),
+                );
+            }
+
+            Ok(query)
+        }
+
+        fn with_substances(
+            rows: Vec<(sea_entity::ingestion::Model, Option<sea_entity::substance::Model>)>,
+        ) -> Result<Vec<(sea_entity::ingestion::Model, sea_entity::substance::Model)>> {
+            rows.into_iter()
+                .map(|(ingestion, substance)| {
+                    substance
+                        .ok_or_else(|| Error::RecordNotFound {
+                            entity: "Substance",
+                            reference: ingestion.substance_id.clone(),
+                        })
+                        .map(|substance| (ingestion, substance))
+                })
+                .collect()
+        }
+
+        /// Ingestions matching given filter together with their substances,
+        /// ordered from the oldest one.
+        pub async fn find_ingestions(
+            filter: &IngestionFilter,
+            db_conn: &DatabaseConnection,
+        ) -> Result<Vec<(sea_entity::ingestion::Model, sea_entity::substance::Model)>> {
+            let rows = filter_ingestions(filter, db_conn)
+                .await?
+                .order_by_asc(db::julian_day(Expr::col(sea_entity::ingestion::Column::IngestedAt)))
+                .all(db_conn)
+                .await?;
+
+            with_substances(rows)
+        }
+
+        /// At most `limit` of the newest ingestions matching given filter, ordered from the newest one.
+        pub(crate) async fn find_latest_ingestions(
+            filter: &IngestionFilter,
+            limit: u64,
+            db_conn: &DatabaseConnection,
+        ) -> Result<Vec<(sea_entity::ingestion::Model, sea_entity::substance::Model)>> {
+            let rows = filter_ingestions(filter, db_conn)
+                .await?
+                .order_by_desc(db::julian_day(Expr::col(sea_entity::ingestion::Column::IngestedAt)))
+                .limit(limit)
+                .all(db_conn)
+                .await?;
+
+            with_substances(rows)
+        }
+
+        pub async fn execute_ingestion_command(
+            ingestion_command: IngestionCommand,
+            db_conn: &DatabaseConnection,
+        ) -> Result<()> {
+            match ingestion_command.command {
+                IngestionCommands::Create(payload) => {
+                    let (ingestion, warnings) = create_ingestion(payload, db_conn).await?;
+                    let substance = find_substance(&ingestion.substance_id, db_conn).await?;
+                    output::print_record(&Ingestion::new(ingestion, substance))?;
+                    print_warnings(&warnings);
+                }
+                IngestionCommands::List(query) => list_ingestions(query, db_conn).await?,
+            }
+
+            Ok(())
+        }
+    }
+
+    pub(super) mod database {
+        use std::collections::{HashMap, HashSet};
+        use std::fs;
+        use std::io;
+        use std::path::PathBuf;
+
+        use clap::{Parser, Subcommand};
+        use sea_orm::{
+            ActiveModelTrait, ActiveValue, ConnectionTrait, DatabaseConnection, EntityTrait,
+            PaginatorTrait, TransactionTrait,
+        };
+        use sea_orm_migration::{IntoSchemaManagerConnection, MigratorTrait};
+        use serde::Serialize;
+        use tabled::Tabled;
+
+        use sea_migration::Migrator;
+
+        use crate::cli::open_journal;
+        use crate::cli::profile::ensure_active_profile_exists;
+        use crate::error::{Error, Result};
+        use crate::{crypto, db, output};
+
+        #[derive(Parser, Debug)]
+        #[command(version, about, long_about = None)]
+        pub struct MergeDatabase {
+            /// Path to other journal database file which should be merged into current one
+            pub path: PathBuf,
+        }
+
+        #[derive(Subcommand)]
+        pub enum DatabaseCommands {
+            /// Merge other journal database file into the current one
+            Merge(MergeDatabase),
+            /// Encrypt journal database with passphrase
+            Encrypt,
+            /// Remove encryption from journal database
+            Decrypt,
+            /// Show where journal database is stored and what it contains
+            Info,
+        }
+
+        #[derive(Parser)]
+        #[command(args_conflicts_with_subcommands = true)]
+        pub(crate) struct DatabaseCommand {
+            #[command(subcommand)]
+            pub command: DatabaseCommands,
+        }
+
+        /// Ingestion from other journal which could not be merged automatically.
+        #[derive(Tabled, Serialize, Debug)]
+        pub(crate) struct MergeConflict {
+            pub id: String,
+            pub substance: String,
+            pub ingested_at: String,
+            pub reason: String,
+        }
+
+        #[derive(Debug, Default)]
+        pub(crate) struct MergeSummary {
+            /// Names of substances which did not exist in target journal
+            pub substances_inserted: Vec<String>,
+            pub substances_matched: usize,
+            pub ingestions_inserted: usize,
+            pub ingestions_skipped: usize,
+            pub conflicts: Vec<MergeConflict>,
+        }
+
+        /// Substance names are matched ignoring case and redundant whitespace,
+        /// so "Caffeine" and " caffeine " are considered the same substance.
+        pub(crate) fn normalize_substance_name(name: &str) -> String {
+            name.split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+                .to_lowercase()
+        }
+
+        fn is_same_dose(
+            ingestion: &sea_entity::ingestion::Model,
+            other: &sea_entity::ingestion::Model,
+        ) -> bool {
+            ingestion.dosage_unit == other.dosage_unit && ingestion.dosage_value == other.dosage_value
+        }
+
+        /// Imports substances and ingestions from `source` journal into `target` one.
+        pub(crate) async fn merge_journal(
+            source: &DatabaseConnection,
+            target: &DatabaseConnection,
+        ) -> Result<MergeSummary> {
+            let substances = sea_entity::substance::Entity::find().all(source).await?;
+            let ingestions = sea_entity::ingestion::Entity::find().all(source).await?;
+
+            import_journal(substances, ingestions, target, false).await
+        }
+
+        /// Picks alias of imported row, original one is kept unless it's already used locally.
+        async fn import_alias<E: EntityTrait>(
+            alias: i32,
+            alias_column: E::Column,
+            used_aliases: &mut HashSet<i32>,
+            database_connection: &impl ConnectionTrait,
+        ) -> Result<i32> {
+            let alias = if used_aliases.contains(&alias) {
+                db::next_alias::<E>(alias_column, database_connection).await?
+            } else {
+                alias
+            };
+
+            used_aliases.insert(alias);
+            Ok(alias)
+        }
+
+        /// Imports substances and ingestions into `target` journal.
+        ///
+        /// Rows keep their globally unique identifiers, timestamps and aliases when these
+        /// are not used locally yet. Substances are matched by identifier or normalized name
+        /// and ingestions which already exist with the same substance, time and dose are skipped.
+        ///
+        /// With `dry_run` all changes are rolled back and only summary of them is returned.
+        pub(crate) async fn import_journal(
+            substances: Vec<sea_entity::substance::Model>,
+            ingestions: Vec<sea_entity::ingestion::Model>,
+            target: &DatabaseConnection,
+            dry_run: bool,
+        ) -> Result<MergeSummary> {
+            let mut summary = MergeSummary::default();
+            let transaction = target.begin().await?;
+
+            let local_substances = sea_entity::substance::Entity::find().all(&transaction).await?;
+            let mut substance_aliases: HashSet<i32> =
+                local_substances.iter().map(|substance| substance.alias).collect();
+            let mut substances_by_id: HashMap<String, String> = local_substances
+                .iter()
+                .map(|substance| (substance.id.clone(), substance.id.clone()))
+                .collect();
+            let mut substances_by_name: HashMap<String, String> = local_substances
+                .iter()
+                .map(|substance| (normalize_substance_name(&substance.name), substance.id.clone()))
+                .collect();
+
+            // Maps identifiers of substances from source journal to their local counterparts.
+            let mut substance_mapping: HashMap<String, String> = HashMap::new();
+            let mut substance_names: HashMap<String, String> = HashMap::new();
+
+            for substance in substances {
+                let normalized_name = normalize_substance_name(&substance.name);
+                substance_names.insert(substance.id.clone(), substance.name.clone());
+
+                let local_id = substances_by_id
+                    .get(&substance.id)
+                    .or_else(|| substances_by_name.get(&normalized_name))
+                    .cloned();
+
+                let local_id = match local_id {
+                    Some(local_id) => {
+                        summary.substances_matched += 1;
+                        local_id
+                    }
+                    None => {
+                        let alias = import_alias::<sea_entity::substance::Entity>(
+                            substance.alias,
+                            sea_entity::substance::Column::Alias,
+                            &mut substance_aliases,
+                            &transaction,
+                        )
+                            .await?;
+
+                        sea_entity::substance::ActiveModel {
+                            id: ActiveValue::Set(substance.id.clone()),
+                            alias: ActiveValue::Set(alias),
+                            name: ActiveValue::Set(substance.name.clone()),
+                            duration_minutes: ActiveValue::Set(substance.duration_minutes),
+                            redose_interval_minutes: ActiveValue::Set(substance.redose_interval_minutes),
+                        }
+                            .insert(&transaction)
+                            .await?;
+
+                        summary.substances_inserted.push(substance.name.clone());
+                        substances_by_id.insert(substance.id.clone(), substance.id.clone());
+                        substances_by_name.insert(normalized_name, substance.id.clone());
+                        substance.id.clone()
+                    }
+                };
+
+                substance_mapping.insert(substance.id, local_id);
+            }
+
+            let local_ingestions = sea_entity::ingestion::Entity::find().all(&transaction).await?;
+            let mut ingestion_aliases: HashSet<i32> =
+                local_ingestions.iter().map(|ingestion| ingestion.alias).collect();
+            let mut ingestions_by_id: HashMap<String, sea_entity::ingestion::Model> = HashMap::new();
+            let mut ingestions_by_moment: HashMap<(String, i64), Vec<sea_entity::ingestion::Model>> =
+                HashMap::new();
+
+            for ingestion in local_ingestions {
+                ingestions_by_moment
+                    .entry((ingestion.substance_id.clone(), ingestion.ingested_at.timestamp_micros()))
+                    .or_default()
+                    .push(ingestion.clone());
+                ingestions_by_id.insert(ingestion.id.clone(), ingestion);
+            }
+
+            for ingestion in ingestions {
+                let substance_id = substance_mapping
+                    .get(&ingestion.substance_id)
+                    .cloned()
+                    .ok_or_else(|| Error::RecordNotFound {
+                        entity: "Substance",
+                        reference: ingestion.substance_id.clone(),
+                    })?;
+
+                let conflict = |reason: &str| MergeConflict {
+                    id: ingestion.id.clone(),
+                    substance: substance_names
+                        .get(&ingestion.substance_id)
+                        .cloned()
+                        .unwrap_or_default(),
+                    ingested_at: ingestion.ingested_at.to_rfc3339(),
+                    reason: reason.to_owned(),
+                };
+
+                if let Some(existing) = ingestions_by_id.get(&ingestion.id) {
+                    if existing.substance_id == substance_id
+                        && existing.ingested_at == ingestion.ingested_at
+                        && is_same_dose(existing, &ingestion)
+                    {
+                        summary.ingestions_skipped += 1;
+                    } else {
+                        summary
+                            .conflicts
+                            .push(conflict("Ingestion with the same identifier differs"));
+                    }
+                    continue;
+                }
+
+                let moment = (substance_id.clone(), ingestion.ingested_at.timestamp_micros());
+                let same_moment = ingestions_by_moment.get(&moment);
+
+                if let Some(same_moment) = same_moment {
+                    if same_moment.iter().any(|existing| is_same_dose(existing, &ingestion)) {
+                        summary.ingestions_skipped += 1;
+                    } else {
+                        summary
+                            .conflicts
+                            .push(conflict("Ingestion at the same time has different dose"));
+                    }
+                    continue;
+                }
+
+                let alias = import_alias::<sea_entity::ingestion::Entity>(
+                    ingestion.alias,
+                    sea_entity::ingestion::Column::Alias,
+                    &mut ingestion_aliases,
+                    &transaction,
+                )
+                    .await?;
+
+                let model = sea_entity::ingestion::ActiveModel {
+                    id: ActiveValue::Set(ingestion.id.clone()),
+                    alias: ActiveValue::Set(alias),
+                    substance_id: ActiveValue::Set(substance_id),
+                    dosage_unit: ActiveValue::Set(ingestion.dosage_unit.clone()),
+                    dosage_value: ActiveValue::Set(ingestion.dosage_value),
+                    route_of_administration: ActiveValue::Set(ingestion.route_of_administration.clone()),
+                    notes: ActiveValue::Set(ingestion.notes.clone()),
+                    ingested_at: ActiveValue::Set(ingestion.ingested_at),
+                    created_at: ActiveValue::Set(ingestion.created_at),
+                    updated_at: ActiveValue::Set(ingestion.updated_at),
+                }
+                    .insert(&transaction)
+                    .await?;
+
+                summary.ingestions_inserted += 1;
+                ingestions_by_moment.entry(moment).or_default().push(model.clone());
+                ingestions_by_id.insert(model.id.clone(), model);
+            }
+
+            if dry_run {
+                transaction.rollback().await?;
+            } else {
+                transaction.commit().await?;
+            }
+
+            Ok(summary)
+        }
+
+        /// Merges journal stored in other database file into the current one.
+        ///
+        /// Other file is copied and migrated to current schema before reading,
+        /// so the file given by user is never modified.
+        pub(crate) async fn merge_database(
+            merge_database: MergeDatabase,
+            database_connection: &DatabaseConnection,
+        ) -> Result<MergeSummary> {
+            if !merge_database.path.is_file() {
+                return Err(Error::InvalidInput(format!(
+                    "Database file {} does not exist",
+                    merge_database.path.display()
+                )));
+            }
+
+            // Copy is migrated instead of the other journal, `fs::copy` is not used
+            // as it would give the private copy permissions of the original file.
+            let source_copy = db::PrivateFile::create("merge")?;
+            let mut source_file = fs::File::open(&merge_database.path)
+                .map_err(Error::io("Failed to read database file"))?;
+            let mut copy_file = fs::OpenOptions::new()
+                .write(true)
+                .open(source_copy.path())
+                .map_err(Error::io("Failed to copy database file"))?;
+            io::copy(&mut source_file, &mut copy_file).map_err(Error::io("Failed to copy database file"))?;
+
+            let source = db::open_database(source_copy.path(), false).await?;
+            let summary = async {
+                Migrator::up(source.into_schema_manager_connection(), None).await?;
+                merge_journal(&source, database_connection).await
+            }
+                .await;
+            source.close().await?;
+
+            summary
+        }
+
+        /// Counts are printed as messages, conflicts which need attention as records.
+        pub(crate) fn print_merge_summary(summary: &MergeSummary) -> Result<()> {
+            output::print_message(format!(
+                "Substances: {} inserted, {} matched",
+                summary.substances_inserted.len(),
+                summary.substances_matched
+            ));
+
+            if !summary.substances_inserted.is_empty() {
+                output::print_message(format!("New substances: {}", summary.substances_inserted.join(", ")));
+            }
+
+            output::print_message(format!(
+                "Ingestions: {} inserted, {} skipped, {} conflicting",
+                summary.ingestions_inserted,
+                summary.ingestions_skipped,
+                summary.conflicts.len()
+            ));
+
+            match summary.conflicts.is_empty() {
+                true => Ok(()),
+                false => output::print_records(&summary.conflicts),
+            }
+        }
+
+        /// Encryption is changed before journal is opened as database file is rewritten.
+        pub async fn execute_database_command(command: DatabaseCommands) -> Result<()> {
+            match command {
+                DatabaseCommands::Merge(payload) => {
+                    let summary = merge_database(payload, &open_journal().await?).await?;
+                    print_merge_summary(&summary)?;
+                }
+                DatabaseCommands::Info => {
+                    let properties = database_info(&open_journal().await?).await?;
+                    output::print_records(&properties)?;
+                }
+                DatabaseCommands::Encrypt => {
+                    ensure_active_profile_exists()?;
+                    change_encryption(true).await?;
+                    output::print_message("Journal database encrypted.");
+                }
+                DatabaseCommands::Decrypt => {
+                    ensure_active_profile_exists()?;
+                    change_encryption(false).await?;
+                    output::print_message("Journal database decrypted.");
+                }
+            }
+
+            Ok(())
+        }
+
+        #[derive(Tabled, Serialize, Debug)]
+        pub(crate) struct DatabaseProperty {
+            pub property: &'static str,
+            pub value: String,
+        }
+
+        pub(crate) fn format_size(bytes: u64) -> String {
+            const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+
+            let mut size = bytes as f64;
+            let mut unit = 0;
+            while size >= 1024.0 && unit < UNITS.len() - 1 {
+                size /= 1024.0;
+                unit += 1;
+            }
+
+            match unit {
+                0 => format!("{} {}", bytes, UNITS[0]),
+                _ => format!("{:.1} {}", size, UNITS[unit]),
+            }
+        }
+
+        /// Location, size and contents of journal database.
+        pub(crate) async fn database_info(
+            database_connection: &DatabaseConnection,
+        ) -> Result<Vec<DatabaseProperty>> {
+            let database_path = db::get_database_path()?;
+
+            // Recently written rows may still be only in write-ahead log.
+            let size: u64 = ["", "-wal"]
+                .iter()
+                .filter_map(|suffix| {
+                    let mut path = database_path.clone().into_os_string();
+                    path.push(suffix);
+                    fs::metadata(path).ok().map(|metadata| metadata.len())
+                })
+                .sum();
+
+            let substances = sea_entity::substance::Entity::find().count(database_connection).await?;
+            let ingestions = sea_entity::ingestion::Entity::find().count(database_connection).await?;
+            let migrations = Migrator::get_applied_migrations(database_connection).await?;
+
+            Ok(vec![
+                DatabaseProperty { property: "path", value: database_path.display().to_string() },
+                DatabaseProperty { property: "location from", value: db::get_database_path_source() },
+                DatabaseProperty { property: "size", value: format_size(size) },
+                DatabaseProperty {
+                    property: "encrypted",
+                    value: db::is_database_encrypted(&database_path).to_string(),
+                },
+                DatabaseProperty { property: "substances", value: substances.to_string() },
+                DatabaseProperty { property: "ingestions", value: ingestions.to_string() },
+                DatabaseProperty { property: "migrations applied", value: migrations.len().to_string() },
+                DatabaseProperty {
+                    property: "backups",
+                    value: db::get_backup_directory()?.display().to_string(),
+                },
+            ])
+        }
+
+        /// Encrypts or decrypts journal database in place, database must not be
+        /// opened beforehand as its file is replaced.
+        pub(crate) async fn change_encryption(encrypt: bool) -> Result<()> {
+            let database_path = db::get_database_path()?;
+
+            if database_path.metadata().map_or(true, |metadata| metadata.len() == 0) {
+                return Err(Error::InvalidOperation("Journal database does not exist yet".to_owned()));
+            }
+
+            match encrypt {
+                true => db::encrypt_database(&database_path, &crypto::read_passphrase(true)?).await,
+                false => db::decrypt_database(&database_path, &crypto::read_passphrase(false)?).await,
+            }
+        }
+    }
+
+    pub(super) mod export {
+        use std::fs;
+        use std::io::{self, Write};
+        use std::path::{Path, PathBuf};
+
+        use age::secrecy::SecretString;
+        use chrono::{DateTime, Utc};
+        use clap::{Parser, Subcommand};
+        use sea_orm::{DatabaseConnection, EntityTrait, QueryOrder};
+        use serde::{Deserialize, Serialize};
+
+        use crate::cli::ingestion::{find_ingestions, IngestionFilter};
+        use crate::cli::substance::find_substance;
+        use crate::error::{Error, Result};
+        use crate::{crypto, output};
+
+        /// Version of JSON journal document, should be increased whenever
+        /// structure of exported rows changes in backward incompatible way.
+        pub(crate) const JOURNAL_FORMAT_VERSION: u32 = 1;
+
+        #[derive(Parser, Debug)]
+        #[command(version, about, long_about = None)]
+        pub struct ExportCsv {
+            /// Directory in which `ingestions.csv` and `substances.csv` will be written,
+            /// when omitted a single table is written to standard output.
+            #[arg(short = 'o', long, value_name = "DIRECTORY")]
+            pub output_directory: Option<PathBuf>,
+            /// Write substances instead of ingestions to standard output,
+            /// substances can be filtered only with `--substance`
+            #[arg(long, conflicts_with_all = ["output_directory", "since", "until"])]
+            pub substances: bool,
+            /// Encrypt output with passphrase
+            #[arg(long)]
+            pub encrypt: bool,
+            #[command(flatten)]
+            pub filter: IngestionFilter,
+        }
+
+        #[derive(Subcommand)]
+        pub enum ExportCommands {
+            /// Export ingestions and substances as CSV
+            Csv(ExportCsv),
+            /// Export whole journal as JSON document which can be restored with `import json`
+            Json(ExportJson),
+        }
+
+        #[derive(Parser)]
+        #[command(args_conflicts_with_subcommands = true)]
+        pub(crate) struct ExportCommand {
+            #[command(subcommand)]
+            pub command: ExportCommands,
+        }
+
+        #[derive(Parser, Debug)]
+        #[command(version, about, long_about = None)]
+        pub struct ExportJson {
+            /// File to which journal will be written, standard output is used by default
+            #[arg(short = 'o', long, value_name = "FILE")]
+            pub output: Option<PathBuf>,
+            /// Write newline-delimited JSON with one row per line instead of a single document
+            #[arg(long)]
+            pub ndjson: bool,
+            /// Encrypt output with passphrase
+            #[arg(long)]
+            pub encrypt: bool,
+        }
+
+        /// Complete journal with rows stored exactly as they are in database.
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        pub(crate) struct JournalDocument {
+            pub version: u32,
+            pub exported_at: DateTime<Utc>,
+            pub substances: Vec<sea_entity::substance::Model>,
+            pub ingestions: Vec<sea_entity::ingestion::Model>,
+        }
+
+        /// Single line of newline-delimited journal, the first line is always a header.
+        #[derive(Serialize, Deserialize, Debug)]
+        #[serde(tag = "kind", rename_all = "snake_case")]
+        pub(crate) enum JournalEntry {
+            Header {
+                version: u32,
+                exported_at: DateTime<Utc>,
+            },
+            Substance(sea_entity::substance::Model),
+            Ingestion(sea_entity::ingestion::Model),
+        }
+
+        #[derive(Serialize, Debug)]
+        pub(crate) struct IngestionRecord {
+            pub id: String,
+            pub alias: i32,
+            pub substance_id: String,
+            pub substance: String,
+            pub dosage_amount: f64,
+            pub dosage_unit: String,
+            pub route_of_administration: Option<String>,
+            pub notes: Option<String>,
+            pub ingested_at: String,
+            pub created_at: String,
+            pub updated_at: String,
+        }
+
+        #[derive(Serialize, Debug)]
+        pub(crate) struct SubstanceRecord {
+            pub id: String,
+            pub alias: i32,
+            pub name: String,
+            pub duration_minutes: Option<i32>,
+            pub redose_interval_minutes: Option<i32>,
+        }
+
+        /// Ingestions matching filter with timestamps formatted as ISO-8601 with offsets.
+        pub(crate) async fn ingestion_records(
+            filter: &IngestionFilter,
+            database_connection: &DatabaseConnection,
+        ) -> Result<Vec<IngestionRecord>> {
+            let ingestions = find_ingestions(filter, database_connection).await?;
+
+            Ok(ingestions
+                .into_iter()
+                .map(|(ingestion, substance)| IngestionRecord {
+                    id: ingestion.id,
+                    alias: ingestion.alias,
+                    substance_id: ingestion.substance_id,
+                    substance: substance.name,
+                    dosage_amount: ingestion.dosage_value,
+                    dosage_unit: ingestion.dosage_unit,
+                    route_of_administration: ingestion.route_of_administration,
+                    notes: ingestion.notes,
+                    ingested_at: ingestion.ingested_at.to_rfc3339(),
+                    created_at: ingestion.created_at.to_rfc3339(),
+                    updated_at: ingestion.updated_at.to_rfc3339(),
+                })
+                .collect())
+        }
+
+        pub(crate) async fn substance_records(
+            filter: &IngestionFilter,
+            database_connection: &DatabaseConnection,
+        ) -> Result<Vec<SubstanceRecord>> {
+            let substances = match &filter.substance {
+                Some(reference) => vec![find_substance(reference, database_connection).await?],
+                None => {
+                    sea_entity::substance::Entity::find()
+                        .order_by_asc(sea_entity::substance::Column::Alias)
+                        .all(database_connection)
+                        .await?
+                }
+            };
+
+            Ok(substances
+                .into_iter()
+                .map(|substance| SubstanceRecord {
+                    id: substance.id,
+                    alias: substance.alias,
+                    name: substance.name,
+                    duration_minutes: substance.duration_minutes,
+                    redose_interval_minutes: substance.redose_interval_minutes,
+                })
+                .collect())
+        }
+
+        pub(crate) fn write_csv<T: Serialize>(
+            records: &[T],
+            writer: impl Write,
+        ) -> Result<(), csv::Error> {
+            let mut csv_writer = csv::Writer::from_writer(writer);
+
+            for record in records {
+                csv_writer.serialize(record)?;
+            }
+
+            csv_writer.flush()?;
+            Ok(())
+        }
+
+        fn csv_content<T: Serialize>(records: &[T]) -> Result<Vec<u8>> {
+            let mut content = Vec::new();
+            write_csv(records, &mut content)
+                .map_err(|err| Error::Serialization(format!("Failed to write CSV: {}", err)))?;
+            Ok(content)
+        }
+
+        /// Writes exported content to file or standard output, encrypting it when
+        /// passphrase is given. Encrypted files get `.age` extension appended.
+        fn write_output(
+            content: Vec<u8>,
+            path: Option<&Path>,
+            passphrase: Option<&SecretString>,
+        ) -> Result<Option<PathBuf>> {
+            let content = match passphrase {
+                Some(passphrase) => crypto::encrypt(&content, passphrase, path.is_none())?,
+                None => content,
+            };
+
+            match path {
+                Some(path) => {
+                    let path = match passphrase {
+                        Some(_) => {
+                            let mut encrypted_path = path.as_os_str().to_owned();
+                            encrypted_path.push(".age");
+                            PathBuf::from(encrypted_path)
+                        }
+                        None => path.to_owned(),
+                    };
+
+                    fs::write(&path, content)
+                        .map_err(Error::io(format!("Failed to write {}", path.display())))?;
+                    Ok(Some(path))
+                }
+                None => {
+                    io::stdout()
+                        .lock()
+                        .write_all(&content)
+                        .map_err(Error::io("Failed to write output"))?;
+                    Ok(None)
+                }
+            }
+        }
+
+        pub async fn export_csv(
+            export_csv: ExportCsv,
+            database_connection: &DatabaseConnection,
+        ) -> Result<()> {
+            let passphrase = export_csv
+                .encrypt
+                .then(|| crypto::read_passphrase(true))
+                .transpose()?;
+
+            match export_csv.output_directory {
+                Some(directory) => {
+                    fs::create_dir_all(&directory)
+                        .map_err(Error::io("Failed to create output directory"))?;
+
+                    let ingestions = ingestion_records(&export_csv.filter, database_connection).await?;
+                    let substances = substance_records(&export_csv.filter, database_connection).await?;
+
+                    for (content, rows, file_name) in [
+                        (csv_content(&ingestions)?, ingestions.len(), "ingestions.csv"),
+                        (csv_content(&substances)?, substances.len(), "substances.csv"),
+                    ] {
+                        if let Some(path) =
+                            write_output(content, Some(&directory.join(file_name)), passphrase.as_ref())?
+                        {
+                            output::print_message(format!("Written {} rows to {}", rows, path.display()));
+                        }
+                    }
+                }
+                None if export_csv.substances => {
+                    let substances = substance_records(&export_csv.filter, database_connection).await?;
+                    write_output(csv_content(&substances)?, None, passphrase.as_ref())?;
+                }
+                None => {
+                    let ingestions = ingestion_records(&export_csv.filter, database_connection).await?;
+                    write_output(csv_content(&ingestions)?, None, passphrase.as_ref())?;
+                }
+            }
+
+            Ok(())
+        }
+
+        pub(crate) async fn journal_document(
+            database_connection: &DatabaseConnection,
+        ) -> Result<JournalDocument> {
+            let substances = sea_entity::substance::Entity::find()
+                .order_by_asc(sea_entity::substance::Column::Alias)
+                .all(database_connection)
+                .await?;
+            let ingestions = sea_entity::ingestion::Entity::find()
+                .order_by_asc(sea_entity::ingestion::Column::Alias)
+                .all(database_connection)
+                .await?;
+
+            Ok(JournalDocument {
+                version: JOURNAL_FORMAT_VERSION,
+                exported_at: Utc::now(),
+                substances,
+                ingestions,
+            })
+        }
+
+        pub(crate) fn write_journal_ndjson(
+            journal: JournalDocument,
+            mut writer: impl Write,
+        ) -> Result<(), serde_json::Error> {
+            let header = JournalEntry::Header {
+                version: journal.version,
+                exported_at: journal.exported_at,
+            };
+
+            let entries = std::iter::once(header)
+                .chain(journal.substances.into_iter().map(JournalEntry::Substance))
+                .chain(journal.ingestions.into_iter().map(JournalEntry::Ingestion));
+
+            for entry in entries {
+                serde_json::to_writer(&mut writer, &entry)?;
+                writeln!(writer).map_err(serde_json::Error::io)?;
+            }
+
+            Ok(())
+        }
+
+        pub async fn export_json(
+            export_json: ExportJson,
+            database_connection: &DatabaseConnection,
+        ) -> Result<()> {
+            let passphrase = export_json
+                .encrypt
+                .then(|| crypto::read_passphrase(true))
+                .transpose()?;
+            let journal = journal_document(database_connection).await?;
+            let to_error =
+                |err: serde_json::Error| Error::Serialization(format!("Failed to write JSON: {}", err));
+
+            let mut content = Vec::new();
+
+            if export_json.ndjson {
+                write_journal_ndjson(journal, &mut content).map_err(to_error)?;
+            } else {
+                serde_json::to_writer_pretty(&mut content, &journal).map_err(to_error)?;
+                content.push(b'\n');
+            }
+
+            if let Some(path) = write_output(content, export_json.output.as_deref(), passphrase.as_ref())? {
+                output::print_message(format!("Journal written to {}", path.display()));
+            }
+
+            Ok(())
+        }
+
+        pub async fn execute_export_command(
+            command: ExportCommands,
+            database_connection: &DatabaseConnection,
+        ) -> Result<()> {
+            match command {
+                ExportCommands::Csv(payload) => export_csv(payload, database_connection).await,
+                ExportCommands::Json(payload) => export_json(payload, database_connection).await,
+            }
+        }
+    }
+
+    pub(super) mod import {
+        use std::collections::HashMap;
+        use std::fs;
+        use std::io::{self, Read};
+        use std::path::PathBuf;
+
+        use chrono::{DateTime, FixedOffset, Local, TimeZone, Utc};
+        use chrono_tz::Tz;
+        use clap::{Parser, Subcommand};
+        use sea_orm::DatabaseConnection;
+        use serde::{Deserialize, Serialize};
+        use tabled::Tabled;
+        use ulid::Ulid;
+
+        use crate::cli::database::{
+            import_journal, normalize_substance_name, print_merge_summary, MergeSummary,
+        };
+        use crate::cli::export::{JournalDocument, JournalEntry, JOURNAL_FORMAT_VERSION};
+        use crate::cli::ingestion::parse_naive_date;
+        use crate::error::{Error, Result};
+        use crate::{config, crypto, output};
+
+        #[derive(Parser, Debug)]
+        #[command(version, about, long_about = None)]
+        pub struct ImportJson {
+            /// JSON document or newline-delimited JSON created by `export json`, use "-" to read standard input
+            pub path: PathBuf,
+        }
+
+        /// Parses mapping of substance name in format `<name>=<substance>`.
+        fn parse_substance_mapping(s: &str) -> Result<(String, String), String> {
+            s.split_once('=')
+                .map(|(name, substance)| (name.trim().to_owned(), substance.trim().to_owned()))
+                .filter(|(name, substance)| !name.is_empty() && !substance.is_empty())
+                .ok_or_else(|| format!("Mapping should be in format <name>=<substance>, got {}", s))
+        }
+
+        /// CSV reader works with bytes, so characters outside ASCII can't separate columns.
+        fn parse_delimiter(s: &str) -> Result<u8, String> {
+            match s.as_bytes() {
+                [delimiter] if delimiter.is_ascii() => Ok(*delimiter),
+                _ => Err(format!("Delimiter should be a single ASCII character, got {}", s)),
+            }
+        }
+
+        #[derive(Parser, Debug)]
+        #[command(version, about, long_about = None)]
+        pub struct ImportPsychonautWiki {
+            /// JSON file exported from PsychonautWiki Journal application
+            pub path: PathBuf,
+            /// Only show what would be imported without saving anything
+            #[arg(long)]
+            pub dry_run: bool,
+            /// Import substance under other name, ex. "Caffeine=coffee", can be repeated
+            #[arg(short = 'm', long = "map", value_name = "NAME=SUBSTANCE", value_parser = parse_substance_mapping)]
+            pub mappings: Vec<(String, String)>,
+        }
+
+        #[derive(Parser, Debug)]
+        #[command(version, about, long_about = None)]
+        pub struct ImportCsv {
+            /// CSV file with header row, use "-" to read standard input
+            pub path: PathBuf,
+            /// Column with name of substance
+            #[arg(long, value_name = "COLUMN", default_value = "substance")]
+            pub substance_column: String,
+            /// Column with amount of substance, amount can include unit such as "100 mg"
+            #[arg(long, value_name = "COLUMN", default_value = "amount")]
+            pub amount_column: String,
+            /// Column with unit of amount
+            #[arg(long, value_name = "COLUMN")]
+            pub unit_column: Option<String>,
+            /// Column with date of ingestion, dates are parsed the same way as in `ingestion create`
+            #[arg(long, value_name = "COLUMN", default_value = "time")]
+            pub time_column: String,
+            /// Column with time zone of date, ex. "Europe/Warsaw" or "+02:00"
+            #[arg(long, value_name = "COLUMN")]
+            pub timezone_column: Option<String>,
+            /// Column with notes about ingestion
+            #[arg(long, value_name = "COLUMN")]
+            pub notes_column: Option<String>,
+            /// Unit used when neither unit column nor amount includes one,
+            /// configured default unit is used if not provided
+            #[arg(long)]
+            pub unit: Option<String>,
+            /// Time zone used when row does not specify one, configured
+            /// or local time zone by default
+            #[arg(long)]
+            pub timezone: Option<String>,
+            /// Single ASCII character separating columns
+            #[arg(long, default_value = ",", value_parser = parse_delimiter)]
+            pub delimiter: u8,
+            /// Only show first parsed rows and rejected lines without saving anything
+            #[arg(long)]
+            pub preview: bool,
+            /// Number of parsed rows shown in preview
+            #[arg(long, default_value_t = 10)]
+            pub preview_rows: usize,
+        }
+
+        #[derive(Subcommand)]
+        pub enum ImportCommands {
+            /// Restore journal exported with `export json`
+            Json(ImportJson),
+            /// Import ingestions from spreadsheet exported as CSV
+            Csv(ImportCsv),
+            /// Import experiences from PsychonautWiki Journal application export
+            #[command(name = "psychonautwiki")]
+            PsychonautWiki(ImportPsychonautWiki),
+        }
+
+        #[derive(Parser)]
+        #[command(args_conflicts_with_subcommands = true)]
+        pub(crate) struct ImportCommand {
+            #[command(subcommand)]
+            pub command: ImportCommands,
+        }
+
+        /// Reads imported file or standard input, encrypted content is decrypted
+        /// with passphrase provided by user.
+        pub(crate) fn read_input(path: &PathBuf) -> Result<String> {
+            let mut content = Vec::new();
+
+            if path.as_os_str() == "-" {
+                io::stdin()
+                    .read_to_end(&mut content)
+                    .map_err(Error::io("Failed to read standard input"))?;
+            } else {
+                content = fs::read(path).map_err(Error::io(format!("Failed to read {}", path.display())))?;
+            }
+
+            if crypto::is_encrypted(&content) {
+                content = crypto::decrypt(&content, &crypto::read_passphrase(false)?)?;
+            }
+
+            String::from_utf8(content)
+                .map_err(|err| Error::InvalidInput(format!("Imported file is not valid UTF-8: {}", err)))
+        }
+
+        /// Parses journal exported either as a single document or as newline-delimited JSON,
+        /// the latter is recognized by header entry in the first line.
+        pub(crate) fn parse_journal(content: &str) -> Result<JournalDocument> {
+            let to_error = |err: serde_json::Error| Error::InvalidInput(format!("Invalid journal: {}", err));
+            let mut lines = content.lines().filter(|line| !line.trim().is_empty());
+
+            let journal = match lines.next().map(serde_json::from_str::<JournalEntry>) {
+                Some(Ok(JournalEntry::Header { version, exported_at })) => {
+                    let mut journal = JournalDocument {
+                        version,
+                        exported_at,
+                        substances: vec![],
+                        ingestions: vec![],
+                    };
+
+                    for line in lines {
+                        match serde_json::from_str::<JournalEntry>(line).map_err(to_error)? {
+                            JournalEntry::Header { .. } => {
+                                return Err(Error::InvalidInput(
+                                    "Invalid journal: unexpected second header".to_owned(),
+                                ));
+                            }
+                            JournalEntry::Substance(substance) => journal.substances.push(substance),
+                            JournalEntry::Ingestion(ingestion) => journal.ingestions.push(ingestion),
+                        }
+                    }
+
+                    journal
+                }
+                _ => serde_json::from_str::<JournalDocument>(content).map_err(to_error)?,
+            };
+
+            if journal.version > JOURNAL_FORMAT_VERSION {
+                return Err(Error::InvalidInput(format!(
+                    "Journal format version {} is not supported, latest supported version is {}",
+                    journal.version, JOURNAL_FORMAT_VERSION
+                )));
+            }
+
+            Ok(journal)
+        }
+
+        #[derive(Deserialize, Debug, Default)]
+        #[serde(rename_all = "camelCase", default)]
+        pub(crate) struct PsychonautWikiExport {
+            pub experiences: Vec<PsychonautWikiExperience>,
+            pub custom_substances: Vec<PsychonautWikiCustomSubstance>,
+            pub custom_units: Vec<PsychonautWikiCustomUnit>,
+        }
+
+        #[derive(Deserialize, Debug, Default)]
+        #[serde(rename_all = "camelCase", default)]
+        pub(crate) struct PsychonautWikiExperience {
+            pub title: String,
+            pub ingestions: Vec<PsychonautWikiIngestion>,
+        }
+
+        /// Ingestion as stored by PsychonautWiki Journal, times are milliseconds since UNIX epoch.
+        #[derive(Deserialize, Debug)]
+        #[serde(rename_all = "camelCase")]
+        pub(crate) struct PsychonautWikiIngestion {
+            pub substance_name: String,
+            pub time: i64,
+            pub creation_date: Option<i64>,
+            pub administration_route: Option<String>,
+            pub dose: Option<f64>,
+            pub units: Option<String>,
+            pub notes: Option<String>,
+            pub custom_unit_id: Option<i64>,
+        }
+
+        #[derive(Deserialize, Debug)]
+        #[serde(rename_all = "camelCase")]
+        pub(crate) struct PsychonautWikiCustomSubstance {
+            pub name: String,
+        }
+
+        /// Unit defined by user such as "cup" of coffee, dose is amount of `original_unit`
+        /// in one custom unit, name of the custom unit itself is kept in `unit`.
+        #[derive(Deserialize, Debug)]
+        #[serde(rename_all = "camelCase")]
+        pub(crate) struct PsychonautWikiCustomUnit {
+            pub id: i64,
+            pub dose: Option<f64>,
+            pub original_unit: String,
+        }
+
+        /// Entry of imported file which could not be converted into ingestion.
+        #[derive(Tabled, Serialize, Debug)]
+        pub(crate) struct RejectedEntry {
+            /// Position of entry in imported file, line number or time of ingestion
+            pub entry: String,
+            pub substance: String,
+            pub reason: String,
+        }
+
+        /// Rows converted from external journal which are ready to be imported.
+        #[derive(Debug, Default)]
+        pub(crate) struct ConvertedJournal {
+            pub substances: Vec<sea_entity::substance::Model>,
+            pub ingestions: Vec<sea_entity::ingestion::Model>,
+            pub rejected: Vec<RejectedEntry>,
+        }
+
+        fn from_timestamp_millis(milliseconds: i64) -> Option<DateTime<FixedOffset>> {
+            DateTime::<Utc>::from_timestamp_millis(milliseconds)
+                .map(|date| date.with_timezone(&Local).fixed_offset())
+        }
+
+        impl ConvertedJournal {
+            /// Finds substance with given name or creates new one, names which are
+            /// mapped by user are replaced before matching.
+            fn substance_id(&mut self, name: &str, mappings: &HashMap<String, String>) -> String {
+                let name = mappings
+                    .get(&normalize_substance_name(name))
+                    .map(String::as_str)
+                    .unwrap_or(name)
+                    .trim();
+
+                if let Some(substance) = self
+                    .substances
+                    .iter()
+                    .find(|substance| normalize_substance_name(&substance.name) == normalize_substance_name(name))
+                {
+                    return substance.id.clone();
+                }
+
+                let substance = sea_entity::substance::Model {
+                    id: Ulid::new().to_string(),
+                    alias: self.substances.len() as i32 + 1,
+                    name: name.to_owned(),
+                    duration_minutes: None,
+                    redose_interval_minutes: None,
+                };
+                let id = substance.id.clone();
+                self.substances.push(substance);
+                id
+            }
+        }
+
+        /// Converts PsychonautWiki Journal export into journal rows, experiences are not
+        /// represented in journal so their titles are kept in notes of ingestions.
+        pub(crate) fn convert_psychonautwiki_export(
+            export: PsychonautWikiExport,
+            mappings: &[(String, String)],
+        ) -> ConvertedJournal {
+            let mappings: HashMap<String, String> = mappings
+                .iter()
+                .map(|(name, substance)| (normalize_substance_name(name), substance.clone()))
+                .collect();
+            let custom_units: HashMap<i64, &PsychonautWikiCustomUnit> =
+                export.custom_units.iter().map(|unit| (unit.id, unit)).collect();
+
+            let mut journal = ConvertedJournal::default();
+
+            for custom_substance in &export.custom_substances {
+                journal.substance_id(&custom_substance.name, &mappings);
+            }
+
+            for experience in &export.experiences {
+                for ingestion in &experience.ingestions {
+                    let reject = |reason: &str| RejectedEntry {
+                        entry: from_timestamp_millis(ingestion.time)
+                            .map(|time| time.to_rfc3339())
+                            .unwrap_or_else(|| ingestion.time.to_string()),
+                        substance: ingestion.substance_name.clone(),
+                        reason: reason.to_owned(),
+                    };
+
+                    let Some(ingested_at) = from_timestamp_millis(ingestion.time) else {
+                        journal.rejected.push(reject("Invalid time of ingestion"));
+                        continue;
+                    };
+
+                    let Some(dose) = ingestion.dose else {
+                        journal.rejected.push(reject("Unknown dose"));
+                        continue;
+                    };
+
+                    let dosage = match ingestion.custom_unit_id {
+                        Some(custom_unit_id) => custom_units
+                            .get(&custom_unit_id)
+                            .and_then(|unit| unit.dose.map(|unit_dose| (dose * unit_dose, unit.original_unit.clone()))),
+                        None => ingestion.units.clone().map(|unit| (dose, unit)),
+                    };
+
+                    let Some((dosage_value, dosage_unit)) = dosage else {
+                        journal.rejected.push(reject("Unknown unit"));
+                        continue;
+                    };
+
+                    let notes = [experience.title.trim(), ingestion.notes.as_deref().unwrap_or_default().trim()]
+                        .into_iter()
+                        .filter(|note| !note.is_empty())
+                        .collect::<Vec<_>>()
+                        .join("\n");
+
+                    let created_at = ingestion
+                        .creation_date
+                        .and_then(from_timestamp_millis)
+                        .unwrap_or(ingested_at);
+
+                    let substance_id = journal.substance_id(&ingestion.substance_name, &mappings);
+
+                    journal.ingestions.push(sea_entity::ingestion::Model {
+                        id: Ulid::from_datetime(ingested_at.into()).to_string(),
+                        alias: journal.ingestions.len() as i32 + 1,
+                        substance_id,
+                        dosage_unit,
+                        dosage_value,
+                        route_of_administration: ingestion
+                            .administration_route
+                            .as_ref()
+                            .map(|route| route.to_lowercase()),
+                        notes: Some(notes).filter(|notes| !notes.is_empty()),
+                        ingested_at,
+                        created_at,
+                        updated_at: created_at,
+                    });
+                }
+            }
+
+            journal
+        }
+
+        /// Splits amount such as "100", "2.5 g" or "0,5mg" into value and optional unit,
+        /// amount has to be positive and comma followed by three digits is refused
+        /// as it could separate thousands as well as decimals.
+        pub(crate) fn parse_amount(value: &str) -> Result<(f64, Option<String>), String> {
+            let value = value.trim();
+            let unit_start = value
+                .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == ',' || c == '-'))
+                .unwrap_or(value.len());
+            let (number, unit) = value.split_at(unit_start);
+
+            if let Some((_, decimals)) = number.split_once(',')
+                && (decimals.len() == 3 || decimals.contains([',', '.']))
+            {
+                return Err(format!("Ambiguous amount {:?}, comma may separate thousands or decimals", value));
+            }
+
+            let number = number
+                .replace(',', ".")
+                .parse::<f64>()
+                .map_err(|_| format!("Invalid amount {:?}", value))?;
+
+            if !number.is_finite() || number <= 0.0 {
+                return Err(format!("Amount {:?} is not positive", value));
+            }
+
+            let unit = unit.trim();
+
+            Ok((number, Some(unit.to_owned()).filter(|unit| !unit.is_empty())))
+        }
+
+        /// Parses date from spreadsheet cell, RFC 3339 timestamps keep their own offset
+        /// while other formats understood by `parse_naive_date` are interpreted in given time zone.
+        pub(crate) fn parse_spreadsheet_date(
+            value: &str,
+            timezone: Option<&str>,
+        ) -> Result<DateTime<FixedOffset>, String> {
+            if let Ok(date) = DateTime::parse_from_rfc3339(value.trim()) {
+                return Ok(date);
+            }
+
+            let naive_date = parse_naive_date(value)?;
+
+            let date = match timezone.map(str::trim).filter(|timezone| !timezone.is_empty()) {
+                None => Local
+                    .from_local_datetime(&naive_date)
+                    .earliest()
+                    .map(|date| date.fixed_offset()),
+                Some(timezone) => match (timezone.parse::<Tz>(), timezone.parse::<FixedOffset>()) {
+                    (Ok(timezone), _) => timezone
+                        .from_local_datetime(&naive_date)
+                        .earliest()
+                        .map(|date| date.fixed_offset()),
+                    (_, Ok(offset)) => offset.from_local_datetime(&naive_date).single(),
+                    _ => return Err(format!("Unknown time zone {}", timezone)),
+                },
+            };
+
+            date.ok_or_else(|| format!("Date {} does not exist in given time zone", value))
+        }
+
+        /// Converts rows of CSV file into journal rows according to column mapping.
+        pub(crate) fn convert_csv(
+            content: &str,
+            import_csv: &ImportCsv,
+        ) -> Result<ConvertedJournal> {
+            let configuration = config::get();
+            let to_error = |err: csv::Error| Error::InvalidInput(format!("Invalid CSV file: {}", err));
+
+            let mut reader = csv::ReaderBuilder::new()
+                .delimiter(import_csv.delimiter)
+                .flexible(true)
+                .from_reader(content.as_bytes());
+
+            let headers = reader.headers().map_err(to_error)?.clone();
+            let column = |name: &str| {
+                headers
+                    .iter()
+                    .position(|header| header.trim().eq_ignore_ascii_case(name.trim()))
+                    .ok_or_else(|| Error::InvalidInput(format!("Column {} was not found in CSV file", name)))
+            };
+            let optional_column = |name: &Option<String>| name.as_deref().map(column).transpose();
+
+            let substance_column = column(&import_csv.substance_column)?;
+            let amount_column = column(&import_csv.amount_column)?;
+            let time_column = column(&import_csv.time_column)?;
+            let unit_column = optional_column(&import_csv.unit_column)?;
+            let timezone_column = optional_column(&import_csv.timezone_column)?;
+            let notes_column = optional_column(&import_csv.notes_column)?;
+
+            let mut journal = ConvertedJournal::default();
+            let mappings = HashMap::new();
+
+            for record in reader.records() {
+                let record = record.map_err(to_error)?;
+                let line = record.position().map(|position| position.line()).unwrap_or_default();
+                let cell = |column: usize| record.get(column).unwrap_or_default().trim();
+                let optional_cell = |column: Option<usize>| {
+                    column.map(cell).filter(|value| !value.is_empty())
+                };
+
+                let substance = cell(substance_column);
+                let reject = |reason: String| RejectedEntry {
+                    entry: format!("line {}", line),
+                    substance: substance.to_owned(),
+                    reason,
+                };
+
+                if substance.is_empty() {
+                    journal.rejected.push(reject("Missing substance".to_owned()));
+                    continue;
+                }
+
+                let (dosage_value, amount_unit) = match parse_amount(cell(amount_column)) {
+                    Ok(amount) => amount,
+                    Err(reason) => {
+                        journal.rejected.push(reject(reason));
+                        continue;
+                    }
+                };
+
+                let timezone = optional_cell(timezone_column)
+                    .or(import_csv.timezone.as_deref())
+                    .or(configuration.timezone.as_deref());
+                let ingested_at = match parse_spreadsheet_date(cell(time_column), timezone) {
+                    Ok(ingested_at) => ingested_at,
+                    Err(reason) => {
+                        journal.rejected.push(reject(reason));
+                        continue;
+                    }
+                };
+
+                let dosage_unit = optional_cell(unit_column)
+                    .map(str::to_owned)
+                    .or(amount_unit)
+                    .unwrap_or_else(|| {
+                        import_csv
+                            .unit
+                            .clone()
+                            .unwrap_or_else(|| configuration.dosage_unit.clone())
+                    });
+
+                let substance_id = journal.substance_id(substance, &mappings);
+                let now = Utc::now().fixed_offset();
+
+                journal.ingestions.push(sea_entity::ingestion::Model {
+                    id: Ulid::from_datetime(ingested_at.into()).to_string(),
+                    alias: journal.ingestions.len() as i32 + 1,
+                    substance_id,
+                    dosage_unit,
+                    dosage_value,
+                    route_of_administration: None,
+                    notes: optional_cell(notes_column).map(str::to_owned),
+                    ingested_at,
+                    created_at: now,
+                    updated_at: now,
+                });
+            }
+
+            Ok(journal)
+        }
+
+        /// Row of import preview with substance name resolved.
+        #[derive(Tabled, Serialize, Debug)]
+        struct PreviewRow {
+            substance: String,
+            amount: f64,
+            unit: String,
+            ingested_at: String,
+            notes: String,
+        }
+
+        fn print_preview(journal: &ConvertedJournal, rows: usize) -> Result<()> {
+            let preview: Vec<PreviewRow> = journal
+                .ingestions
+                .iter()
+                .take(rows)
+                .map(|ingestion| PreviewRow {
+                    substance: journal
+                        .substances
+                        .iter()
+                        .find(|substance| substance.id == ingestion.substance_id)
+                        .map(|substance| substance.name.clone())
+                        .unwrap_or_default(),
+                    amount: ingestion.dosage_value,
+                    unit: ingestion.dosage_unit.clone(),
+                    ingested_at: ingestion.ingested_at.to_rfc3339(),
+                    notes: ingestion.notes.clone().unwrap_or_default(),
+                })
+                .collect();
+
+            output::print_message(format!(
+                "Parsed {} rows, showing first {}:",
+                journal.ingestions.len(),
+                preview.len()
+            ));
+            output::print_records(&preview)
+        }
+
+        fn print_rejected(rejected: &[RejectedEntry]) -> Result<()> {
+            if rejected.is_empty() {
+                return Ok(());
+            }
+
+            output::print_message(format!("Rejected entries: {}", rejected.len()));
+            output::print_records(rejected)
+        }
+
+        pub async fn import_csv(
+            import_csv: ImportCsv,
+            database_connection: &DatabaseConnection,
+        ) -> Result<()> {
+            let journal = convert_csv(&read_input(&import_csv.path)?, &import_csv)?;
+
+            if import_csv.preview {
+                print_preview(&journal, import_csv.preview_rows)?;
+            }
+
+            let summary = import_journal(
+                journal.substances,
+                journal.ingestions,
+                database_connection,
+                import_csv.preview,
+            )
+                .await?;
+
+            print_merge_summary(&summary)?;
+            print_rejected(&journal.rejected)?;
+
+            if import_csv.preview {
+                output::print_message("Preview only, nothing was saved.");
+            }
+
+            Ok(())
+        }
+
+        pub async fn import_psychonautwiki(
+            import_psychonautwiki: ImportPsychonautWiki,
+            database_connection: &DatabaseConnection,
+        ) -> Result<(MergeSummary, Vec<RejectedEntry>)> {
+            let export: PsychonautWikiExport =
+                serde_json::from_str(&read_input(&import_psychonautwiki.path)?).map_err(|err| {
+                    Error::InvalidInput(format!("Invalid PsychonautWiki Journal export: {}", err))
+                })?;
+
+            let journal = convert_psychonautwiki_export(export, &import_psychonautwiki.mappings);
+            let summary = import_journal(
+                journal.substances,
+                journal.ingestions,
+                database_connection,
+                import_psychonautwiki.dry_run,
+            )
+                .await?;
+
+            Ok((summary, journal.rejected))
+        }
+
+        pub async fn import_json(
+            import_json: ImportJson,
+            database_connection: &DatabaseConnection,
+        ) -> Result<MergeSummary> {
+            let journal = parse_journal(&read_input(&import_json.path)?)?;
+            import_journal(journal.substances, journal.ingestions, database_connection, false).await
+        }
+
+        pub async fn execute_import_command(
+            command: ImportCommands,
+            database_connection: &DatabaseConnection,
+        ) -> Result<()> {
+            match command {
+                ImportCommands::Json(payload) => {
+                    let summary = import_json(payload, database_connection).await?;
+                    print_merge_summary(&summary)?;
+                }
+                ImportCommands::Csv(payload) => {
+                    import_csv(payload, database_connection).await?;
+                }
+                ImportCommands::PsychonautWiki(payload) => {
+                    let dry_run = payload.dry_run;
+                    let (summary, rejected) = import_psychonautwiki(payload, database_connection).await?;
+                    print_merge_summary(&summary)?;
+                    print_rejected(&rejected)?;
+
+                    if dry_run {
+                        output::print_message("Dry run, nothing was saved.");
+
+                        if !summary.substances_inserted.is_empty() {
+                            output::print_message(
+                                "Unknown substances can be mapped onto existing ones with --map \"<name>=<substance>\".",
+                            );
+                        }
+                    }
+                }
+            }
+
+            Ok(())
+        }
+    }
+
+    pub(super) mod backup {
+        use std::fs;
+        use std::path::{Path, PathBuf};
+
+        use clap::Parser;
+        use sea_orm::DatabaseConnection;
+
+        use crate::error::{Error, Result};
+        use crate::{crypto, db, output};
+
+        #[derive(Parser, Debug)]
+        #[command(version, about, long_about = None)]
+        pub struct Backup {
+            /// File or directory in which backup will be created,
+            /// by default backup is stored in application data directory
+            pub path: Option<PathBuf>,
+            /// Encrypt backup with passphrase
+            #[arg(long)]
+            pub encrypt: bool,
+        }
+
+        #[derive(Parser, Debug)]
+        #[command(version, about, long_about = None)]
+        pub struct Restore {
+            /// Backup file which will replace current journal
+            pub path: PathBuf,
+        }
+
+        pub async fn create_backup(
+            backup: Backup,
+            database_connection: &DatabaseConnection,
+        ) -> Result<PathBuf> {
+            let default_path = match backup.encrypt {
+                true => db::timestamped_backup_path("backup")?.with_extension("db.age"),
+                false => db::timestamped_backup_path("backup")?,
+            };
+
+            let backup_path = match (backup.path, default_path.file_name()) {
+                (Some(path), Some(file_name)) if path.is_dir() => path.join(file_name),
+                (Some(path), _) => path,
+                (None, _) => default_path,
+            };
+
+            if !backup.encrypt {
+                db::backup_database(database_connection, &backup_path).await?;
+                return Ok(backup_path);
+            }
+
+            if backup_path.exists() {
+                return Err(Error::InvalidOperation(format!(
+                    "Backup file {} already exists",
+                    backup_path.display()
+                )));
+            }
+
+            let passphrase = crypto::read_passphrase(true)?;
+
+            let snapshot = db::snapshot_database(database_connection).await?;
+            let snapshot = fs::read(snapshot.path()).map_err(Error::io("Failed to read snapshot"))?;
+            fs::write(&backup_path, crypto::encrypt(&snapshot, &passphrase, false)?)
+                .map_err(Error::io("Failed to write backup"))?;
+
+            Ok(backup_path)
+        }
+
+        /// Replaces current journal with backup after making sure it can be used
+        /// by this version of application, current journal is backed up beforehand.
+        pub async fn restore_backup(restore: Restore) -> Result<Option<PathBuf>> {
+            if !restore.path.is_file() {
+                return Err(Error::InvalidInput(format!(
+                    "Backup file {} does not exist",
+                    restore.path.display()
+                )));
+            }
+
+            let content = fs::read(&restore.path).map_err(Error::io("Failed to read backup"))?;
+
+            if !crypto::is_encrypted(&content) {
+                return replace_database(&restore.path).await;
+            }
+
+            let decrypted = crypto::decrypt(&content, &crypto::read_passphrase(false)?)?;
+            let decrypted_file = db::PrivateFile::create("restore")?;
+            fs::write(decrypted_file.path(), decrypted)
+                .map_err(Error::io("Failed to write decrypted backup"))?;
+
+            replace_database(decrypted_file.path()).await
+        }
+
+        async fn replace_database(backup_path: &Path) -> Result<Option<PathBuf>> {
+            let backup = db::open_database(backup_path, true).await?;
+            let validation = db::validate_journal_database(&backup).await;
+            backup.close().await?;
+            let pending_migrations = validation?;
+
+            let database_path = db::get_database_path()?;
+            let mut previous_journal = None;
+
+            if database_path.metadata().is_ok_and(|metadata| metadata.len() > 0) {
+                let current = db::open_database(&database_path, false).await?;
+                let backup_path = db::timestamped_backup_path("pre-restore")?;
+                let result = db::backup_database(&current, &backup_path).await;
+                current.close().await?;
+                result?;
+                previous_journal = Some(backup_path);
+            }
+
+            // Copy next to the journal first so the journal is replaced atomically.
+            let restored_path = database_path.with_extension("db.restore");
+            fs::copy(backup_path, &restored_path).map_err(Error::io("Failed to replace journal database"))?;
+            db::replace_database_file(&restored_path, &database_path)
+                .map_err(Error::io("Failed to replace journal database"))?;
+
+            if pending_migrations > 0 {
+                output::print_message(format!(
+                    "Backup is missing {} migrations which will be applied on next run.",
+                    pending_migrations
+                ));
+            }
+
+            Ok(previous_journal)
+        }
+    }
+
+    pub(super) mod profile {
+        use std::fs;
+        use std::io::{self, BufRead, Write};
+        use std::path::PathBuf;
+
+        use clap::{Parser, Subcommand};
+        use sea_orm_migration::{IntoSchemaManagerConnection, MigratorTrait};
+        use serde::Serialize;
+        use tabled::Tabled;
+
+        use sea_migration::Migrator;
+
+        use crate::cli::database::format_size;
+        use crate::{db, output};
+        use crate::error::{Error, Result};
+
+        #[derive(Parser, Debug)]
+        #[command(version, about, long_about = None)]
+        pub struct CreateProfile {
+            /// Name of profile, only letters, digits, "-" and "_" are allowed
+            pub name: String,
+        }
+
+        #[derive(Parser, Debug)]
+        #[command(version, about, long_about = None)]
+        pub struct RenameProfile {
+            pub name: String,
+            pub new_name: String,
+        }
+
+        #[derive(Parser, Debug)]
+        #[command(version, about, long_about = None)]
+        pub struct DeleteProfile {
+            pub name: String,
+            /// Do not ask for confirmation
+            #[arg(long)]
+            pub yes: bool,
+        }
+
+        #[derive(Subcommand)]
+        pub enum ProfileCommands {
+            /// List profiles and their journals
+            List,
+            /// Create profile with empty journal
+            Create(CreateProfile),
+            /// Rename profile together with its backups
+            Rename(RenameProfile),
+            /// Delete journal of profile, its backups are kept
+            Delete(DeleteProfile),
+        }
+
+        #[derive(Parser)]
+        #[command(args_conflicts_with_subcommands = true)]
+        pub(crate) struct ProfileCommand {
+            #[command(subcommand)]
+            pub command: ProfileCommands,
+        }
+
+        #[derive(Tabled, Serialize, Debug)]
+        pub(crate) struct Profile {
+            pub name: String,
+            pub active: String,
+            pub size: String,
+            pub path: String,
+        }
+
+        pub(crate) fn validate_profile_name(name: &str) -> Result<()> {
+            let is_valid = !name.is_empty()
+                && name
+                    .chars()
+                    .all(|character| character.is_ascii_alphanumeric() || character == '-' || character == '_');
+
+            match is_valid {
+                true => Ok(()),
+                false => Err(Error::InvalidInput(format!(
+                    "Invalid profile name \"{}\", only letters, digits, \"-\" and \"_\" are allowed",
+                    name
+                ))),
+            }
+        }
+
+        fn profile_exists(name: &str) -> bool {
+            validate_profile_name(name).is_ok() && db::get_profile_database_path(name).is_ok_and(|path| path.is_file())
+        }
+
+        pub(crate) fn ensure_profile_exists(name: &str) -> Result<()> {
+            match name == db::DEFAULT_PROFILE || profile_exists(name) {
+                true => Ok(()),
+                false => Err(Error::InvalidInput(format!(
+                    "Profile {} does not exist, create it with `profile create {}`",
+                    name, name
+                ))),
+            }
+        }
+
+        /// Journal of active profile has to be created with `profile create` first,
+        /// unless database was chosen explicitly.
+        pub(crate) fn ensure_active_profile_exists() -> Result<()> {
+            match db::get_active_profile() {
+                Some(active_profile) => ensure_profile_exists(active_profile),
+                None => Ok(()),
+            }
+        }
+
+        /// Default profile followed by other ones in alphabetical order.
+        pub(crate) fn list_profiles() -> Result<Vec<Profile>> {
+            let mut names = vec![db::DEFAULT_PROFILE.to_owned()];
+
+            let profiles_directory = db::get_data_directory()?.join("profiles");
+            if profiles_directory.is_dir() {
+                let mut other_names: Vec<String> = fs::read_dir(&profiles_directory)
+                    .map_err(Error::io("Failed to list profiles"))?
+                    .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                    .filter(|name| name != db::DEFAULT_PROFILE && profile_exists(name))
+                    .collect();
+                other_names.sort();
+                names.extend(other_names);
+            }
+
+            let active_profile = db::get_active_profile();
+
+            names
+                .into_iter()
+                .map(|name| {
+                    let path = db::get_profile_database_path(&name)?;
+                    Ok(Profile {
+                        active: match active_profile == Some(name.as_str()) {
+                            true => String::from("*"),
+                            false => String::new(),
+                        },
+                        size: fs::metadata(&path)
+                            .map(|metadata| format_size(metadata.len()))
+                            .unwrap_or_else(|_| String::from("-")),
+                        path: path.display().to_string(),
+                        name,
+                    })
+                })
+                .collect()
+        }
+
+        /// Directory of existing profile other than default one, name is validated before
+        /// it's used in path so it can't point outside of `profiles` directory.
+        fn profile_directory(name: &str) -> Result<PathBuf> {
+            validate_profile_name(name)?;
+
+            if name == db::DEFAULT_PROFILE {
+                return Err(Error::InvalidOperation("Default profile has no profile directory".to_owned()));
+            }
+
+            ensure_profile_exists(name)?;
+
+            let profiles_directory = db::get_data_directory()?.join("profiles");
+            let directory = profiles_directory.join(name);
+            let is_inside_profiles = match (directory.canonicalize(), profiles_directory.canonicalize()) {
+                (Ok(directory), Ok(profiles_directory)) => directory.parent() == Some(profiles_directory.as_path()),
+                _ => false,
+            };
+
+            match directory.is_dir() && is_inside_profiles {
+                true => Ok(directory),
+                false => Err(Error::InvalidOperation(format!("Profile {} has no profile directory", name))),
+            }
+        }
+
+        pub(crate) async fn create_profile(create_profile: CreateProfile) -> Result<()> {
+            validate_profile_name(&create_profile.name)?;
+
+            if create_profile.name == db::DEFAULT_PROFILE || profile_exists(&create_profile.name) {
+                return Err(Error::InvalidOperation(format!(
+                    "Profile {} already exists",
+                    create_profile.name
+                )));
+            }
+
+            let database_path = db::get_profile_database_path(&create_profile.name)?;
+            if let Some(directory) = database_path.parent() {
+                fs::create_dir_all(directory).map_err(Error::io("Failed to create profile"))?;
+            }
+
+            let database_connection = db::connect_database(&database_path, false, None).await?;
+            let migration = Migrator::up(database_connection.into_schema_manager_connection(), None).await;
+            database_connection.close().await?;
+            migration.map_err(Error::MigrationFailed)
+        }
+
+        pub(crate) fn rename_profile(rename_profile: RenameProfile) -> Result<()> {
+            validate_profile_name(&rename_profile.new_name)?;
+
+            if rename_profile.name == db::DEFAULT_PROFILE || rename_profile.new_name == db::DEFAULT_PROFILE {
+                return Err(Error::InvalidOperation("Default profile cannot be renamed".to_owned()));
+            }
+
+            let directory = profile_directory(&rename_profile.name)?;
+            let new_directory = db::get_data_directory()?.join("profiles").join(&rename_profile.new_name);
+
+            if new_directory.exists() {
+                return Err(Error::InvalidOperation(format!(
+                    "Profile {} already exists",
+                    rename_profile.new_name
+                )));
+            }
+
+            fs::rename(directory, new_directory).map_err(Error::io("Failed to rename profile"))
+        }
+
+        fn confirm(question: &str) -> Result<bool> {
+            print!("{} [y/N] ", question);
+            io::stdout().flush().ok();
+
+            let mut answer = String::new();
+            io::stdin()
+                .lock()
+                .read_line(&mut answer)
+                .map_err(Error::io("Failed to read answer"))?;
+
+            Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+        }
+
+        /// Removes journal of profile, backups made of it stay in profile directory.
+        pub(crate) fn delete_profile(delete_profile: DeleteProfile) -> Result<bool> {
+            if delete_profile.name == db::DEFAULT_PROFILE {
+                return Err(Error::InvalidOperation("Default profile cannot be deleted".to_owned()));
+            }
+
+            let directory = profile_directory(&delete_profile.name)?;
+
+            if !delete_profile.yes
+                && !confirm(&format!("Delete journal of profile {}?", delete_profile.name))?
+            {
+                return Ok(false);
+            }
+
+            let database_path = directory.join("data.db");
+            for suffix in ["", "-wal", "-shm"] {
+                let mut path = database_path.clone().into_os_string();
+                path.push(suffix);
+                let _ = fs::remove_file(path);
+            }
+
+            Ok(true)
+        }
+
+        pub async fn execute_profile_command(command: ProfileCommands) -> Result<()> {
+            match command {
+                ProfileCommands::List => {
+                    let profiles = list_profiles()?;
+                    output::print_records(&profiles)?;
+                }
+                ProfileCommands::Create(payload) => {
+                    let name = payload.name.clone();
+                    create_profile(payload).await?;
+                    output::print_message(format!("Profile {} created.", name));
+                }
+                ProfileCommands::Rename(payload) => {
+                    let new_name = payload.new_name.clone();
+                    rename_profile(payload)?;
+                    output::print_message(format!("Profile renamed to {}.", new_name));
+                }
+                ProfileCommands::Delete(payload) => {
+                    let name = payload.name.clone();
+                    match delete_profile(payload)? {
+                        true => output::print_message(format!(
+                            "Profile {} deleted, its backups were kept in {}.",
+                            name,
+                            db::get_data_directory()?.join("profiles").join(&name).display()
+                        )),
+                        false => output::print_message(format!("Profile {} was not deleted.", name)),
+                    }
+                }
+            }
+
+            Ok(())
+        }
+    }
+
+    pub(super) mod stats {
+        use std::collections::BTreeMap;
+
+        use chrono::{Datelike, Days, Local, Months, NaiveDate, NaiveTime, Timelike, Weekday};
+        use clap::{Parser, ValueEnum};
+        use sea_orm::DatabaseConnection;
+        use serde::Serialize;
+        use tabled::Tabled;
+
+        use crate::cli::chart::bar;
+        use crate::cli::ingestion::{find_ingestions, normalize_dosage, IngestionFilter};
+        use crate::error::Result;
+        use crate::output::{self, display_option, OutputFormat};
+
+        /// Width of histogram bars in distribution.
+        const HISTOGRAM_WIDTH: usize = 30;
+
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+        pub enum Period {
+            Day,
+            /// Weeks starting on Monday
+            Week,
+            Month,
+        }
+
+        impl Period {
+            pub(crate) fn start_of(self, date: NaiveDate) -> NaiveDate {
+                match self {
+                    Period::Day => date,
+                    Period::Week => date - Days::new(date.weekday().num_days_from_monday() as u64),
+                    Period::Month => NaiveDate::from_ymd_opt(date.year(), date.month(), 1).unwrap_or(date),
+                }
+            }
+
+            pub(crate) fn next(self, start: NaiveDate) -> NaiveDate {
+                match self {
+                    Period::Day => start + Days::new(1),
+                    Period::Week => start + Days::new(7),
+                    Period::Month => start + Months::new(1),
+                }
+            }
+
+            pub(crate) fn label(self, start: NaiveDate) -> String {
+                match self {
+                    Period::Day => start.to_string(),
+                    Period::Week => {
+                        let week = start.iso_week();
+                        format!("{}-W{:02}", week.year(), week.week())
+                    }
+                    Period::Month => start.format("%Y-%m").to_string(),
+                }
+            }
+        }
+
+        #[derive(Parser, Debug)]
+        #[command(version, about, long_about = None)]
+        pub struct Stats {
+            /// Length of periods in which ingestions are aggregated
+            #[arg(long, value_enum, default_value_t = Period::Week)]
+            pub period: Period,
+            /// Show when ingestions happen by hour of day, weekday and month instead of amounts
+            #[arg(long, conflicts_with = "period")]
+            pub distribution: bool,
+            #[command(flatten)]
+            pub filter: IngestionFilter,
+        }
+
+        /// Single dose with amount converted by [`normalize_dosage`].
+        #[derive(Debug, Clone)]
+        pub(crate) struct Dose {
+            pub substance: String,
+            pub unit: String,
+            pub amount: f64,
+            pub date: NaiveDate,
+            pub time: NaiveTime,
+        }
+
+        /// Consumption of substance in one period, mean, min and max are missing
+        /// for periods without ingestions and change is relative to previous period.
+        #[derive(Tabled, Serialize, Debug, Clone, PartialEq)]
+        pub(crate) struct SubstanceStatistics {
+            pub substance: String,
+            pub period: String,
+            pub count: usize,
+            pub total: f64,
+            #[tabled(display_with = "display_option")]
+            pub mean: Option<f64>,
+            #[tabled(display_with = "display_option")]
+            pub min: Option<f64>,
+            #[tabled(display_with = "display_option")]
+            pub max: Option<f64>,
+            pub unit: String,
+            pub days_used: i64,
+            pub days_abstinent: i64,
+            /// Average daily amount over 7 days ending with the period
+            pub average_7d: f64,
+            /// Average daily amount over 30 days ending with the period
+            pub average_30d: f64,
+            /// Change of total amount against previous period in percent
+            #[tabled(display_with = "display_change")]
+            pub change: Option<f64>,
+        }
+
+        fn display_change(change: &Option<f64>) -> String {
+            match change {
+                Some(change) => format!("{:+}%", change),
+                None => String::from("-"),
+            }
+        }
+
+        pub(crate) fn round(value: f64) -> f64 {
+            (value * 100.0).round() / 100.0
+        }
+
+        /// Doses grouped by substance and unit, units of one substance which cannot
+        /// be converted into each other are kept apart.
+        pub(crate) fn group_doses(doses: &[Dose]) -> BTreeMap<(String, String), Vec<&Dose>> {
+            let mut groups: BTreeMap<(String, String), Vec<&Dose>> = BTreeMap::new();
+            for dose in doses {
+                groups
+                    .entry((dose.substance.clone(), dose.unit.clone()))
+                    .or_default()
+                    .push(dose);
+            }
+            groups
+        }
+
+        pub(crate) fn daily_totals(doses: &[&Dose]) -> BTreeMap<NaiveDate, f64> {
+            let mut daily_totals: BTreeMap<NaiveDate, f64> = BTreeMap::new();
+            for dose in doses {
+                *daily_totals.entry(dose.date).or_default() += dose.amount;
+            }
+            daily_totals
+        }
+
+        fn rolling_average(daily_totals: &BTreeMap<NaiveDate, f64>, end: NaiveDate, days: u64) -> f64 {
+            let start = end - Days::new(days - 1);
+            round(daily_totals.range(start..=end).map(|(_, amount)| amount).sum::<f64>() / days as f64)
+        }
+
+        /// Aggregates doses of every substance and unit from the period of its first dose
+        /// up to the period containing `until`, days before `since` are not counted as abstinent.
+        pub(crate) fn calculate_statistics(
+            doses: &[Dose],
+            period: Period,
+            since: Option<NaiveDate>,
+            until: NaiveDate,
+        ) -> Vec<SubstanceStatistics> {
+            let mut statistics = Vec::new();
+
+            for ((substance, unit), doses) in group_doses(doses) {
+                let daily_totals = daily_totals(&doses);
+
+                let Some(first_date) = daily_totals.keys().next().copied() else {
+                    continue;
+                };
+
+                let mut previous_total = None;
+                let mut start = period.start_of(first_date);
+
+                while start <= until {
+                    let next_start = period.next(start);
+                    let range_start = since.map_or(start, |since| since.max(start));
+                    let range_end = until.min(next_start - Days::new(1));
+
+                    let amounts: Vec<f64> = doses
+                        .iter()
+                        .filter(|dose| dose.date >= start && dose.date < next_start)
+                        .map(|dose| dose.amount)
+                        .collect();
+                    let total: f64 = amounts.iter().sum();
+                    let days_used = daily_totals.range(start..next_start).count() as i64;
+                    let days_in_range = (range_end - range_start).num_days() + 1;
+
+                    statistics.push(SubstanceStatistics {
+                        substance: substance.clone(),
+                        period: period.label(start),
+                        count: amounts.len(),
+                        total: round(total),
+                        mean: (!amounts.is_empty()).then(|| round(total / amounts.len() as f64)),
+                        min: amounts.iter().copied().reduce(f64::min).map(round),
+                        max: amounts.iter().copied().reduce(f64::max).map(round),
+                        unit: unit.clone(),
+                        days_used,
+                        days_abstinent: (days_in_range - days_used).max(0),
+                        average_7d: rolling_average(&daily_totals, range_end, 7),
+                        average_30d: rolling_average(&daily_totals, range_end, 30),
+                        change: previous_total
+                            .filter(|previous_total| *previous_total > 0.0)
+                            .map(|previous_total| round((total - previous_total) / previous_total * 100.0)),
+                    });
+
+                    previous_total = Some(total);
+                    start = next_start;
+                }
+            }
+
+            statistics
+        }
+
+        /// Ingestions matching filter as doses dated in local time zone.
+        pub(crate) async fn load_doses(
+            filter: &IngestionFilter,
+            database_connection: &DatabaseConnection,
+        ) -> Result<Vec<Dose>> {
+            Ok(find_ingestions(filter, database_connection)
+                .await?
+                .into_iter()
+                .map(|(ingestion, substance)| {
+                    let (amount, unit) = normalize_dosage(ingestion.dosage_value, &ingestion.dosage_unit);
+                    let ingested_at = ingestion.ingested_at.with_timezone(&Local);
+                    Dose {
+                        substance: substance.name,
+                        unit,
+                        amount,
+                        date: ingested_at.date_naive(),
+                        time: ingested_at.time(),
+                    }
+                })
+                .collect())
+        }
+
+        /// Number of ingestions of substance falling into one bucket of distribution, `kind` is
+        /// one of "all", "hour", "weekday" and "month". Drift is change of median time against
+        /// previous month in minutes.
+        #[derive(Tabled, Serialize, Debug, Clone, PartialEq)]
+        pub(crate) struct DistributionBucket {
+            pub substance: String,
+            pub kind: &'static str,
+            pub bucket: String,
+            pub count: usize,
+            #[tabled(display_with = "display_option")]
+            pub median_time: Option<String>,
+            #[tabled(display_with = "display_option")]
+            pub drift_minutes: Option<i64>,
+        }
+
+        /// Median of times of day, times are not wrapped around midnight so doses taken
+        /// shortly after midnight count as early ones.
+        fn median_time(times: &[NaiveTime]) -> Option<NaiveTime> {
+            let mut seconds: Vec<u32> = times.iter().map(|time| time.num_seconds_from_midnight()).collect();
+            seconds.sort_unstable();
+
+            let middle = seconds.len() / 2;
+            let median = match seconds.len() {
+                0 => return None,
+                length if length % 2 == 0 => (seconds[middle - 1] + seconds[middle]) / 2,
+                _ => seconds[middle],
+            };
+
+            NaiveTime::from_num_seconds_from_midnight_opt(median, 0)
+        }
+
+        fn format_time(time: NaiveTime) -> String {
+            time.format("%H:%M").to_string()
+        }
+
+        /// Distribution of ingestions of every substance over hours of day (only hours between
+        /// the earliest and the latest used one), weekdays and months.
+        pub(crate) fn calculate_distribution(doses: &[Dose]) -> Vec<DistributionBucket> {
+            let mut substances: BTreeMap<&str, Vec<&Dose>> = BTreeMap::new();
+            for dose in doses {
+                substances.entry(dose.substance.as_str()).or_default().push(dose);
+            }
+
+            let mut buckets = Vec::new();
+
+            for (substance, doses) in substances {
+                let times_of = |predicate: &dyn Fn(&Dose) -> bool| -> Vec<NaiveTime> {
+                    doses.iter().filter(|dose| predicate(dose)).map(|dose| dose.time).collect()
+                };
+                let bucket = |kind: &'static str, bucket: String, times: &[NaiveTime]| DistributionBucket {
+                    substance: substance.to_owned(),
+                    kind,
+                    bucket,
+                    count: times.len(),
+                    median_time: median_time(times).map(format_time),
+                    drift_minutes: None,
+                };
+
+                let all_times = times_of(&|_| true);
+                buckets.push(bucket("all", String::from("all"), &all_times));
+
+                let hours = doses.iter().map(|dose| dose.time.hour());
+                if let (Some(first_hour), Some(last_hour)) = (hours.clone().min(), hours.max()) {
+                    for hour in first_hour..=last_hour {
+                        let times = times_of(&|dose| dose.time.hour() == hour);
+                        buckets.push(DistributionBucket {
+                            median_time: None,
+                            ..bucket("hour", format!("{:02}", hour), &times)
+                        });
+                    }
+                }
+
+                for weekday in (0..7).filter_map(|day| Weekday::try_from(day).ok()) {
+                    let times = times_of(&|dose| dose.date.weekday() == weekday);
+                    buckets.push(bucket("weekday", weekday.to_string(), &times));
+                }
+
+                let mut months: BTreeMap<String, Vec<NaiveTime>> = BTreeMap::new();
+                for dose in &doses {
+                    months.entry(dose.date.format("%Y-%m").to_string()).or_default().push(dose.time);
+                }
+
+                let mut previous_median: Option<NaiveTime> = None;
+                for (month, times) in months {
+                    let median = median_time(&times);
+                    buckets.push(DistributionBucket {
+                        drift_minutes: previous_median
+                            .zip(median)
+                            .map(|(previous, median)| (median - previous).num_minutes()),
+                        ..bucket("month", month, &times)
+                    });
+                    previous_median = median;
+                }
+            }
+
+            buckets
+        }
+
+        /// Histograms of distribution grouped by substance.
+        fn print_distribution(buckets: &[DistributionBucket]) {
+            for bucket in buckets {
+                let max = buckets
+                    .iter()
+                    .filter(|other| other.substance == bucket.substance && other.kind == bucket.kind)
+                    .map(|other| other.count)
+                    .max()
+                    .unwrap_or_default();
+                let histogram = bar(bucket.count as f64, max as f64, HISTOGRAM_WIDTH);
+                let median_time = bucket.median_time.as_deref().unwrap_or("-");
+
+                match bucket.kind {
+                    "all" => println!(
+                        "\n{}, {} ingestions, median time {}",
+                        bucket.substance, bucket.count, median_time
+                    ),
+                    "hour" => println!("  {:<7} {:>4} {}", format!("{}:00", bucket.bucket), bucket.count, histogram),
+                    "weekday" => println!(
+                        "  {:<7} {:>4} {:HISTOGRAM_WIDTH$} {}",
+                        bucket.bucket, bucket.count, histogram, median_time
+                    ),
+                    _ => println!(
+                        "  {:<7} {:>4} median {}{}",
+                        bucket.bucket,
+                        bucket.count,
+                        median_time,
+                        bucket
+                            .drift_minutes
+                            .map(|drift| format!(" ({:+} min)", drift))
+                            .unwrap_or_default()
+                    ),
+                }
+            }
+        }
+
+        /// Last day covered by statistics, `--until` or today unless there are later doses.
+        pub(crate) fn last_day(filter: &IngestionFilter, doses: &[Dose]) -> NaiveDate {
+            let last_date = doses.iter().map(|dose| dose.date).max().unwrap_or_default();
+            filter
+                .until
+                .map(|until| until.date_naive())
+                .unwrap_or_else(|| Local::now().date_naive())
+                .max(last_date)
+        }
+
+        pub async fn execute_stats_command(stats: Stats, database_connection: &DatabaseConnection) -> Result<()> {
+            let doses = load_doses(&stats.filter, database_connection).await?;
+
+            if doses.is_empty() {
+                output::print_message("There are no ingestions matching given filters.");
+                return Ok(());
+            }
+
+            if stats.distribution {
+                let distribution = calculate_distribution(&doses);
+                return match output::format() {
+                    OutputFormat::Table => {
+                        print_distribution(&distribution);
+                        Ok(())
+                    }
+                    _ => output::print_records(&distribution),
+                };
+            }
+
+            let until = last_day(&stats.filter, &doses);
+            let since = stats.filter.since.map(|since| since.date_naive());
+
+            output::print_records(&calculate_statistics(&doses, stats.period, since, until))
+        }
+    }
+
+    pub(super) mod chart {
+        use std::collections::BTreeMap;
+
+        use chrono::{Datelike, Days, NaiveDate};
+        use clap::{Parser, ValueEnum};
+        use sea_orm::DatabaseConnection;
+        use serde::Serialize;
+        use tabled::Tabled;
+
+        use crate::cli::ingestion::IngestionFilter;
+        use crate::cli::stats::{daily_totals, group_doses, last_day, load_doses, Period};
+        use crate::error::Result;
+        use crate::output::{self, OutputFormat};
+
+        const SPARKLINE_LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+        const BAR_EIGHTHS: [char; 7] = ['▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+        const HEATMAP_LEVELS: [char; 5] = ['·', '░', '▒', '▓', '█'];
+
+        /// Width used when output is not a terminal and `COLUMNS` is not set.
+        const DEFAULT_WIDTH: usize = 80;
+        const DEFAULT_BAR_DAYS: usize = 14;
+        const HEATMAP_WEEKS: usize = 53;
+
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+        pub enum ChartKind {
+            /// Daily totals of every substance on a single line
+            Sparkline,
+            /// Bar of daily total for every day and substance
+            Bar,
+            /// Calendar of ingestions over the last year
+            Heatmap,
+        }
+
+        #[derive(Parser, Debug)]
+        #[command(version, about, long_about = None)]
+        pub struct Chart {
+            #[arg(value_enum, default_value_t = ChartKind::Sparkline)]
+            pub kind: ChartKind,
+            /// Number of days shown, by default sparklines fill terminal width and bar charts show 14 days
+            #[arg(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+            pub days: Option<usize>,
+            #[command(flatten)]
+            pub filter: IngestionFilter,
+        }
+
+        /// Data behind charts, printed instead of them with formats other than table.
+        #[derive(Tabled, Serialize, Debug)]
+        pub(crate) struct DailyTotal {
+            pub substance: String,
+            pub date: NaiveDate,
+            pub total: f64,
+            pub unit: String,
+        }
+
+        fn terminal_width() -> usize {
+            terminal_size::terminal_size()
+                .map(|(terminal_size::Width(width), _)| width as usize)
+                .or_else(|| std::env::var("COLUMNS").ok()?.parse().ok())
+                .unwrap_or(DEFAULT_WIDTH)
+        }
+
+        /// Daily totals of days ending with `end`, days without ingestions are zero.
+        fn window(daily_totals: &BTreeMap<NaiveDate, f64>, end: NaiveDate, days: usize) -> Vec<(NaiveDate, f64)> {
+            (0..days as u64)
+                .rev()
+                .map(|offset| end - Days::new(offset))
+                .map(|date| (date, daily_totals.get(&date).copied().unwrap_or_default()))
+                .collect()
+        }
+
+        /// Days without ingestions are left blank, any other day is drawn at least with the lowest block.
+        pub(crate) fn sparkline(values: &[f64]) -> String {
+            let max = values.iter().copied().fold(0.0, f64::max);
+
+            values
+                .iter()
+                .map(|&value| match value > 0.0 {
+                    true => {
+                        let level = (value / max * SPARKLINE_LEVELS.len() as f64).ceil() as usize;
+                        SPARKLINE_LEVELS[level.clamp(1, SPARKLINE_LEVELS.len()) - 1]
+                    }
+                    false => ' ',
+                })
+                .collect()
+        }
+
+        /// Bar with length proportional to value, drawn with eighths of a character.
+        pub(crate) fn bar(value: f64, max: f64, width: usize) -> String {
+            if value <= 0.0 || max <= 0.0 {
+                return String::new();
+            }
+
+            let eighths = (value / max * width as f64 * 8.0).round().max(1.0) as usize;
+            let (full, partial) = (eighths / 8, eighths % 8);
+            let mut bar = "█".repeat(full);
+            if partial > 0 {
+                bar.push(BAR_EIGHTHS[partial - 1]);
+            }
+            bar
+        }
+
+        /// Calendar with a row for every day of week and a column for every week ending with
+        /// the one containing `end`, shade of day depends on number of ingestions.
+        pub(crate) fn heatmap(counts: &BTreeMap<NaiveDate, usize>, end: NaiveDate, weeks: usize) -> Vec<String> {
+            const LABEL_WIDTH: usize = 4;
+
+            let first_week = Period::Week.start_of(end) - Days::new(7 * (weeks as u64).saturating_sub(1));
+            let max = counts
+                .range(first_week..=end)
+                .map(|(_, count)| *count)
+                .max()
+                .unwrap_or_default();
+
+            let mut months = vec![' '; weeks];
+            let mut previous_month = None;
+            for week in 0..weeks {
+                let week_start = first_week + Days::new(7 * week as u64);
+                if previous_month != Some(week_start.month()) && week + 3 <= weeks {
+                    if week == 0 || months[week - 1] == ' ' {
+                        for (offset, character) in week_start.format("%b").to_string().chars().enumerate() {
+                            months[week + offset] = character;
+                        }
+                    }
+                    previous_month = Some(week_start.month());
+                }
+            }
+
+            let mut lines = vec![format!(
+                "{:LABEL_WIDTH$}{}",
+                "",
+                months.into_iter().collect::<String>().trim_end()
+            )];
+
+            for (weekday, label) in ["Mon", "", "Wed", "", "Fri", "", "Sun"].iter().enumerate() {
+                let cells: String = (0..weeks)
+                    .map(|week| first_week + Days::new((7 * week + weekday) as u64))
+                    .map(|date| match counts.get(&date).copied().unwrap_or_default() {
+                        _ if date > end => ' ',
+                        0 => HEATMAP_LEVELS[0],
+                        count => {
+                            let level = (count as f64 / max as f64 * 4.0).ceil() as usize;
+                            HEATMAP_LEVELS[level.clamp(1, 4)]
+                        }
+                    })
+                    .collect();
+                lines.push(format!("{:LABEL_WIDTH$}{}", label, cells.trim_end()));
+            }
+
+            lines
+        }
+
+        pub async fn execute_chart_command(chart: Chart, database_connection: &DatabaseConnection) -> Result<()> {
+            let doses = load_doses(&chart.filter, database_connection).await?;
+
+            if doses.is_empty() {
+                output::print_message("There are no ingestions matching given filters.");
+                return Ok(());
+            }
+
+            let end = last_day(&chart.filter, &doses);
+            let groups = group_doses(&doses);
+            let width = terminal_width();
+            let label_width = groups.keys().map(|(substance, _)| substance.chars().count()).max().unwrap_or_default();
+
+            if output::format() != OutputFormat::Table {
+                let totals: Vec<DailyTotal> = groups
+                    .iter()
+                    .flat_map(|((substance, unit), doses)| {
+                        daily_totals(doses).into_iter().map(|(date, total)| DailyTotal {
+                            substance: substance.clone(),
+                            date,
+                            total,
+                            unit: unit.clone(),
+                        })
+                    })
+                    .collect();
+                return output::print_records(&totals);
+            }
+
+            match chart.kind {
+                ChartKind::Sparkline => {
+                    // Space is left for label, maximum and unit.
+                    let days = chart.days.unwrap_or_else(|| width.saturating_sub(label_width + 16).max(7));
+                    println!(
+                        "{:label_width$} {} – {}",
+                        "",
+                        end - Days::new(days as u64 - 1),
+                        end
+                    );
+
+                    for ((substance, unit), doses) in &groups {
+                        let values: Vec<f64> = window(&daily_totals(doses), end, days)
+                            .into_iter()
+                            .map(|(_, total)| total)
+                            .collect();
+                        let max = values.iter().copied().fold(0.0, f64::max);
+                        println!("{:label_width$} {} max {} {}", substance, sparkline(&values), max, unit);
+                    }
+                }
+                ChartKind::Bar => {
+                    let days = chart.days.unwrap_or(DEFAULT_BAR_DAYS);
+
+                    for ((substance, unit), doses) in &groups {
+                        let totals = window(&daily_totals(doses), end, days);
+                        let max = totals.iter().map(|(_, total)| *total).fold(0.0, f64::max);
+                        let value_width = totals.iter().map(|(_, total)| total.to_string().len()).max().unwrap_or_default();
+                        // Space is left for date, value and unit.
+                        let bar_width = width.saturating_sub(value_width + unit.len() + 14).max(10);
+
+                        println!("{} ({})", substance, unit);
+                        for (date, total) in totals {
+                            let bar = bar(total, max, bar_width);
+                            match total > 0.0 {
+                                true => println!("{} {} {} {}", date, bar, total, unit),
+                                false => println!("{}", date),
+                            }
+                        }
+                        println!();
+                    }
+                }
+                ChartKind::Heatmap => {
+                    let mut counts: BTreeMap<NaiveDate, usize> = BTreeMap::new();
+                    for dose in &doses {
+                        *counts.entry(dose.date).or_default() += 1;
+                    }
+
+                    let weeks = HEATMAP_WEEKS.min(width.saturating_sub(4)).max(1);
+                    for line in heatmap(&counts, end, weeks) {
+                        println!("{}", line);
+                    }
+
+                    let first_day = Period::Week.start_of(end) - Days::new(7 * (weeks as u64 - 1));
+                    println!(
+                        "\n    Less {} More, {} days with ingestions since {}",
+                        HEATMAP_LEVELS.iter().collect::<String>(),
+                        counts.range(first_day..=end).count(),
+                        first_day
+                    );
+                }
+            }
+
+            Ok(())
+        }
+    }
+
+    pub(super) mod limits {
+        use std::collections::BTreeSet;
+        use std::fmt;
+
+        use chrono::{DateTime, Local, NaiveDate, TimeDelta, TimeZone, Utc};
+        use clap::{Parser, Subcommand, ValueEnum};
+        use clap_complete::engine::ArgValueCompleter;
+        use sea_orm::{
+            ActiveModelTrait, ActiveValue, ColumnTrait, Condition, DatabaseConnection, EntityTrait, ModelTrait,
+            QueryFilter, QueryOrder,
+        };
+        use serde::Serialize;
+        use tabled::Tabled;
+        use ulid::Ulid;
+
+        use crate::cli::ingestion::{normalize_dosage, IngestionFilter};
+        use crate::cli::completions::complete_substances;
+        use crate::cli::stats::{load_doses, round, Dose, Period};
+        use crate::cli::substance::find_substance;
+        use crate::config::{self, LimitPolicy};
+        use crate::error::{Error, Result};
+        use crate::{db, output};
+
+        #[derive(Parser, Debug)]
+        #[command(version, about, long_about = None)]
+        pub struct SetLimit {
+            /// Alias, identifier or name of substance
+            #[arg(short = 's', long, add = ArgValueCompleter::new(complete_substances))]
+            pub substance: String,
+            /// Maximal amount of substance in period, ex. `--amount 400 --unit mg`
+            #[arg(long, required_unless_present = "days", conflicts_with = "days")]
+            pub amount: Option<f64>,
+            /// Unit of amount, configured default unit is used if not provided
+            #[arg(short = 'u', long, requires = "amount")]
+            pub unit: Option<String>,
+            /// Maximal number of days with any ingestion in period, ex. `--days 3 --per week`
+            #[arg(long)]
+            pub days: Option<i32>,
+            /// Period in which limit applies
+            #[arg(long, value_enum, default_value_t = Period::Day)]
+            pub per: Period,
+        }
+
+        #[derive(Parser, Debug)]
+        #[command(version, about, long_about = None)]
+        pub struct RemoveLimit {
+            /// Alias or identifier of limit
+            pub id: String,
+        }
+
+        #[derive(Subcommand)]
+        pub enum LimitsCommands {
+            /// Show usage of every limit in current period
+            List,
+            /// Set limit of substance, replaces existing limit of the same kind and period
+            Set(SetLimit),
+            /// Remove limit
+            Remove(RemoveLimit),
+        }
+
+        #[derive(Parser)]
+        #[command(args_conflicts_with_subcommands = true)]
+        pub(crate) struct LimitsCommand {
+            /// Limits are listed when no command is given
+            #[command(subcommand)]
+            pub command: Option<LimitsCommands>,
+        }
+
+        #[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+        #[serde(rename_all = "lowercase")]
+        pub(crate) enum LimitStatus {
+            Ok,
+            /// Usage reached `warnings.limit_ratio` of limit
+            Approaching,
+            Exceeded,
+        }
+
+        impl fmt::Display for LimitStatus {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self {
+                    LimitStatus::Ok => write!(f, "ok"),
+                    LimitStatus::Approaching => write!(f, "approaching"),
+                    LimitStatus::Exceeded => write!(f, "exceeded"),
+                }
+            }
+        }
+
+        /// Usage of limit in period containing given day, `window` names that period.
+        #[derive(Tabled, Serialize, Debug, Clone, PartialEq)]
+        pub(crate) struct LimitUsage {
+            pub alias: i32,
+            pub substance: String,
+            pub per: String,
+            pub window: String,
+            pub used: f64,
+            pub maximum: f64,
+            pub unit: String,
+            pub percent: f64,
+            pub status: LimitStatus,
+        }
+
+        fn parse_period(limit: &sea_entity::dosage_limit::Model) -> Result<Period> {
+            Period::from_str(&limit.period, true)
+                .map_err(|_| Error::InvalidInput(format!("Limit {} has unknown period {}", limit.alias, limit.period)))
+        }
+
+        /// Usage of limit among given doses, amount limits count only doses in unit of limit
+        /// and days limits count days with any dose.
+        pub(crate) fn limit_usage(
+            limit: &sea_entity::dosage_limit::Model,
+            substance: &str,
+            date: NaiveDate,
+            doses: &[Dose],
+            ratio: f64,
+        ) -> Result<LimitUsage> {
+            let period = parse_period(limit)?;
+
+            let (used, maximum, unit) = match (limit.max_amount, limit.max_days) {
+                (Some(max_amount), _) => {
+                    let unit = limit.unit.clone().unwrap_or_default();
+                    let used = doses.iter().filter(|dose| dose.unit == unit).map(|dose| dose.amount).sum();
+                    (used, max_amount, unit)
+                }
+                (None, Some(max_days)) => {
+                    let days: BTreeSet<NaiveDate> = doses.iter().map(|dose| dose.date).collect();
+                    (days.len() as f64, f64::from(max_days), String::from("days"))
+                }
+                (None, None) => {
+                    return Err(Error::InvalidInput(format!("Limit {} has neither amount nor days", limit.alias)));
+                }
+            };
+
+            let status = match used {
+                used if used > maximum => LimitStatus::Exceeded,
+                used if used >= maximum * ratio => LimitStatus::Approaching,
+                _ => LimitStatus::Ok,
+            };
+
+            Ok(LimitUsage {
+                alias: limit.alias,
+                substance: substance.to_owned(),
+                per: limit.period.clone(),
+                window: period.label(period.start_of(date)),
+                used: round(used),
+                maximum,
+                unit,
+                percent: round(used / maximum * 100.0),
+                status,
+            })
+        }
+
+        fn start_of_day(date: NaiveDate) -> DateTime<Local> {
+            let midnight = date.and_time(Default::default());
+            Local
+                .from_local_datetime(&midnight)
+                .earliest()
+                .unwrap_or_else(|| Local.from_utc_datetime(&midnight))
+        }
+
+        /// Doses of substance in period of limit which contains given time.
+        async fn doses_in_period(
+            limit: &sea_entity::dosage_limit::Model,
+            at: DateTime<Local>,
+            database_connection: &DatabaseConnection,
+        ) -> Result<Vec<Dose>> {
+            let period = parse_period(limit)?;
+            let start = period.start_of(at.date_naive());
+
+            let filter = IngestionFilter {
+                substance: Some(limit.substance_id.clone()),
+                since: Some(start_of_day(start)),
+                until: Some(start_of_day(period.next(start)) - TimeDelta::seconds(1)),
+            };
+
+            load_doses(&filter, database_connection).await
+        }
+
+        /// Usage of limits of substance which new ingestion would approach or exceed, when
+        /// `limit_policy` is "refuse" exceeding ingestion is rejected unless it's forced.
+        pub(crate) async fn check_limits(
+            substance: &sea_entity::substance::Model,
+            dosage_amount: f64,
+            dosage_unit: &str,
+            ingested_at: DateTime<Local>,
+            force: bool,
+            database_connection: &DatabaseConnection,
+        ) -> Result<Vec<LimitUsage>> {
+            let configuration = config::get();
+            let limits = substance
+                .find_related(sea_entity::dosage_limit::Entity)
+                .all(database_connection)
+                .await?;
+
+            let (amount, unit) = normalize_dosage(dosage_amount, dosage_unit);
+            let new_dose = Dose {
+                substance: substance.name.clone(),
+                unit,
+                amount,
+                date: ingested_at.date_naive(),
+                time: ingested_at.time(),
+            };
+
+            let mut warnings = Vec::new();
+
+            for limit in limits {
+                let mut doses = doses_in_period(&limit, ingested_at, database_connection).await?;
+                doses.push(new_dose.clone());
+
+                let usage = limit_usage(
+                    &limit,
+                    &substance.name,
+                    new_dose.date,
+                    &doses,
+                    configuration.warnings.limit_ratio,
+                )?;
+
+                match usage.status {
+                    LimitStatus::Exceeded if configuration.limit_policy == LimitPolicy::Refuse && !force => {
+                        return Err(Error::LimitExceeded(format!(
+                            "Ingestion would exceed limit of {} {} {} per {}, {} {} would be used in {}",
+                            usage.substance, usage.maximum, usage.unit, usage.per, usage.used, usage.unit, usage.window
+                        )));
+                    }
+                    LimitStatus::Exceeded | LimitStatus::Approaching => warnings.push(usage),
+                    LimitStatus::Ok => {}
+                }
+            }
+
+            Ok(warnings)
+        }
+
+        /// Warning about limit which is approached or exceeded by ingestion.
+        pub(crate) fn describe_warning(usage: &LimitUsage) -> String {
+            let description = format!(
+                "limit of {} {} {} per {}",
+                usage.substance, usage.maximum, usage.unit, usage.per
+            );
+
+            match usage.status {
+                LimitStatus::Exceeded => format!(
+                    "Ingestion exceeds {}, {} {} used in {}",
+                    description, usage.used, usage.unit, usage.window
+                ),
+                _ => format!(
+                    "Ingestion approaches {}, {} {} ({}%) used in {}",
+                    description, usage.used, usage.unit, usage.percent, usage.window
+                ),
+            }
+        }
+
+        pub(crate) fn print_warnings(warnings: &[LimitUsage]) {
+            for usage in warnings {
+                output::print_message(describe_warning(usage));
+            }
+        }
+
+        pub async fn set_limit(
+            set_limit: SetLimit,
+            database_connection: &DatabaseConnection,
+        ) -> Result<sea_entity::dosage_limit::Model> {
+            let substance = find_substance(&set_limit.substance, database_connection).await?;
+
+            let (max_amount, unit, max_days) = match (set_limit.amount, set_limit.days) {
+                (Some(amount), None) if amount.is_finite() && amount > 0.0 => {
+                    let unit = set_limit.unit.unwrap_or_else(|| config::get().dosage_unit.clone());
+                    let (amount, unit) = normalize_dosage(amount, &unit);
+                    (Some(amount), Some(unit), None)
+                }
+                (None, Some(days)) if days > 0 => (None, None, Some(days)),
+                _ => {
+                    return Err(Error::InvalidInput(String::from(
+                        "Limit needs either positive --amount or positive --days",
+                    )));
+                }
+            };
+
+            let period = set_limit
+                .per
+                .to_possible_value()
+                .map(|value| value.get_name().to_owned())
+                .unwrap_or_default();
+
+            let existing = sea_entity::dosage_limit::Entity::find()
+                .filter(sea_entity::dosage_limit::Column::SubstanceId.eq(&substance.id))
+                .filter(sea_entity::dosage_limit::Column::Period.eq(&period))
+                .all(database_connection)
+                .await?
+                .into_iter()
+                .find(|limit| limit.max_days.is_some() == max_days.is_some());
+
+            let is_new = existing.is_none();
+            let mut active_model = match existing {
+                Some(existing) => existing.into(),
+                None => sea_entity::dosage_limit::ActiveModel {
+                    id: ActiveValue::Set(Ulid::new().to_string()),
+                    alias: ActiveValue::Set(
+                        db::next_alias::<sea_entity::dosage_limit::Entity>(
+                            sea_entity::dosage_limit::Column::Alias,
+                            database_connection,
+                        )
+                            .await?,
+                    ),
+                    substance_id: ActiveValue::Set(substance.id),
+                    period: ActiveValue::Set(period),
+                    created_at: ActiveValue::Set(Utc::now().into()),
+                    ..Default::default()
+                },
+            };
+
+            active_model.max_amount = ActiveValue::Set(max_amount);
+            active_model.unit = ActiveValue::Set(unit);
+            active_model.max_days = ActiveValue::Set(max_days);
+            active_model.updated_at = ActiveValue::Set(Utc::now().into());
+
+            match is_new {
+                true => Ok(active_model.insert(database_connection).await?),
+                false => Ok(active_model.update(database_connection).await?),
+            }
+        }
+
+        pub async fn remove_limit(remove_limit: RemoveLimit, database_connection: &DatabaseConnection) -> Result<()> {
+            let mut condition = Condition::any().add(sea_entity::dosage_limit::Column::Id.eq(&remove_limit.id));
+            if let Ok(alias) = remove_limit.id.parse::<i32>() {
+                condition = condition.add(sea_entity::dosage_limit::Column::Alias.eq(alias));
+            }
+
+            let limit = sea_entity::dosage_limit::Entity::find()
+                .filter(condition)
+                .one(database_connection)
+                .await?
+                .ok_or_else(|| Error::RecordNotFound {
+                    entity: "Limit",
+                    reference: remove_limit.id.clone(),
+                })?;
+
+            limit.delete(database_connection).await?;
+            Ok(())
+        }
+
+        /// Usage of every limit in period containing current time.
+        pub async fn list_limits(database_connection: &DatabaseConnection) -> Result<Vec<LimitUsage>> {
+            let now = Local::now();
+            let limits = sea_entity::dosage_limit::Entity::find()
+                .find_also_related(sea_entity::substance::Entity)
+                .order_by_asc(sea_entity::dosage_limit::Column::Alias)
+                .all(database_connection)
+                .await?;
+
+            let mut usages = Vec::new();
+            for (limit, substance) in limits {
+                let substance = substance.map(|substance| substance.name).unwrap_or_default();
+                let doses = doses_in_period(&limit, now, database_connection).await?;
+                usages.push(limit_usage(
+                    &limit,
+                    &substance,
+                    now.date_naive(),
+                    &doses,
+                    config::get().warnings.limit_ratio,
+                )?);
+            }
+
+            Ok(usages)
+        }
+
+        pub async fn execute_limits_command(
+            command: Option<LimitsCommands>,
+            database_connection: &DatabaseConnection,
+        ) -> Result<()> {
+            match command.unwrap_or(LimitsCommands::List) {
+                LimitsCommands::List => {
+                    let usages = list_limits(database_connection).await?;
+                    match usages.is_empty() {
+                        true => output::print_message("There are no limits, add one with `limits set`."),
+                        false => output::print_records(&usages)?,
+                    }
+                }
+                LimitsCommands::Set(payload) => {
+                    let limit = set_limit(payload, database_connection).await?;
+                    let usage = list_limits(database_connection)
+                        .await?
+                        .into_iter()
+                        .find(|usage| usage.alias == limit.alias);
+                    if let Some(usage) = usage {
+                        output::print_record(&usage)?;
+                    }
+                }
+                LimitsCommands::Remove(payload) => {
+                    let id = payload.id.clone();
+                    remove_limit(payload, database_connection).await?;
+                    output::print_message(format!("Limit {} removed.", id));
+                }
+            }
+
+            Ok(())
+        }
+    }
+
+    pub(super) mod streaks {
+        use std::collections::BTreeMap;
+
+        use chrono::{Local, NaiveDate, NaiveDateTime, TimeDelta};
+        use clap::Parser;
+        use clap_complete::engine::ArgValueCompleter;
+        use sea_orm::DatabaseConnection;
+        use serde::Serialize;
+        use tabled::Tabled;
+
+        use crate::cli::completions::complete_substances;
+        use crate::cli::ingestion::IngestionFilter;
+        use crate::cli::stats::load_doses;
+        use crate::error::Result;
+        use crate::output;
+
+        /// Shortest abstinence in days after which next ingestion is counted as relapse.
+        pub(crate) const DEFAULT_MIN_STREAK: i64 = 2;
+
+        #[derive(Parser, Debug)]
+        #[command(version, about, long_about = None)]
+        pub struct Streaks {
+            /// Only streaks of substance with given alias, identifier or name
+            #[arg(short = 's', long, add = ArgValueCompleter::new(complete_substances))]
+            pub substance: Option<String>,
+            /// Show history of relapses instead of summary
+            #[arg(long)]
+            pub relapses: bool,
+            /// Shortest abstinence in days after which ingestion counts as relapse
+            #[arg(long, default_value_t = DEFAULT_MIN_STREAK)]
+            pub min_streak: i64,
+        }
+
+        /// Ingestion which ended abstinence of at least minimal streak.
+        #[derive(Tabled, Serialize, Debug, Clone, PartialEq)]
+        pub(crate) struct Relapse {
+            pub substance: String,
+            pub date: NaiveDate,
+            pub abstinent_days: i64,
+        }
+
+        /// Streaks are counted in whole days without ingestion, current streak ends today.
+        #[derive(Tabled, Serialize, Debug, Clone, PartialEq)]
+        pub(crate) struct SubstanceStreaks {
+            pub substance: String,
+            pub last_use: String,
+            pub since_last_use: String,
+            pub current_streak: i64,
+            pub longest_streak: i64,
+            pub relapses: usize,
+        }
+
+        /// Duration rounded to minutes, ex. "2d 5h 10m".
+        pub(crate) fn format_duration(duration: TimeDelta) -> String {
+            let minutes = duration.num_minutes().max(0);
+            let (days, hours, minutes) = (minutes / 1440, minutes % 1440 / 60, minutes % 60);
+
+            match (days, hours) {
+                (0, 0) => format!("{}m", minutes),
+                (0, _) => format!("{}h {}m", hours, minutes),
+                _ => format!("{}d {}h {}m", days, hours, minutes),
+            }
+        }
+
+        /// Streaks of substance from its times of use, which don't have to be ordered.
+        pub(crate) fn calculate_streaks(
+            substance: &str,
+            uses: &[NaiveDateTime],
+            now: NaiveDateTime,
+            min_streak: i64,
+        ) -> Option<(SubstanceStreaks, Vec<Relapse>)> {
+            let last_use = uses.iter().max().copied()?;
+
+            let mut days: Vec<NaiveDate> = uses.iter().map(|time| time.date()).collect();
+            days.sort_unstable();
+            days.dedup();
+
+            let mut relapses = Vec::new();
+            let mut longest_streak = 0;
+
+            for pair in days.windows(2) {
+                let abstinent_days = (pair[1] - pair[0]).num_days() - 1;
+                longest_streak = longest_streak.max(abstinent_days);
+
+                if abstinent_days >= min_streak.max(1) {
+                    relapses.push(Relapse {
+                        substance: substance.to_owned(),
+                        date: pair[1],
+                        abstinent_days,
+                    });
+                }
+            }
+
+            let current_streak = (now.date() - last_use.date()).num_days().max(0);
+
+            Some((
+                SubstanceStreaks {
+                    substance: substance.to_owned(),
+                    last_use: last_use.format("%Y-%m-%d %H:%M").to_string(),
+                    since_last_use: format_duration(now - last_use),
+                    current_streak,
+                    longest_streak: longest_streak.max(current_streak),
+                    relapses: relapses.len(),
+                },
+                relapses,
+            ))
+        }
+
+        /// Streaks of every substance with at least one ingestion, ordered by name.
+        pub(crate) async fn find_streaks(
+            substance: Option<String>,
+            min_streak: i64,
+            database_connection: &DatabaseConnection,
+        ) -> Result<Vec<(SubstanceStreaks, Vec<Relapse>)>> {
+            let filter = IngestionFilter {
+                substance,
+                ..Default::default()
+            };
+
+            let mut uses: BTreeMap<String, Vec<NaiveDateTime>> = BTreeMap::new();
+            for dose in load_doses(&filter, database_connection).await? {
+                uses.entry(dose.substance).or_default().push(dose.date.and_time(dose.time));
+            }
+
+            let now = Local::now().naive_local();
+            Ok(uses
+                .iter()
+                .filter_map(|(substance, uses)| calculate_streaks(substance, uses, now, min_streak))
+                .collect())
+        }
+
+        pub async fn execute_streaks_command(streaks: Streaks, database_connection: &DatabaseConnection) -> Result<()> {
+            let (summaries, relapses): (Vec<SubstanceStreaks>, Vec<Vec<Relapse>>) =
+                find_streaks(streaks.substance, streaks.min_streak, database_connection)
+                    .await?
+                    .into_iter()
+                    .unzip();
+
+            match streaks.relapses {
+                true => output::print_records(&relapses.concat()),
+                false => output::print_records(&summaries),
+            }
+        }
+    }
+
+    pub(super) mod redose {
+        use std::fmt::{Display, Formatter};
+
+        use chrono::{Local, NaiveDateTime, TimeDelta};
+        use clap::Parser;
+        use clap_complete::engine::ArgValueCompleter;
+        use sea_orm::DatabaseConnection;
+        use serde::Serialize;
+        use tabled::Tabled;
+
+        use crate::cli::completions::complete_substances;
+        use crate::cli::ingestion::IngestionFilter;
+        use crate::cli::stats::{load_doses, round, Dose};
+        use crate::cli::streaks::format_duration;
+        use crate::cli::substance::find_substance;
+        use crate::error::Result;
+        use crate::output::{self, display_option};
+
+        #[derive(Parser, Debug)]
+        #[command(version, about, long_about = None)]
+        pub struct Redose {
+            /// Alias, identifier or name of substance
+            #[arg(add = ArgValueCompleter::new(complete_substances))]
+            pub substance: String,
+        }
+
+        #[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+        #[serde(rename_all = "snake_case")]
+        pub(crate) enum RedoseStatus {
+            /// Minimum redose interval since last dose did not pass yet.
+            TooEarly,
+            /// Last dose is still within its duration, redose would stack with it.
+            Active,
+            /// Last dose is no longer active.
+            Clear,
+            /// Substance has neither duration nor redose interval.
+            Unknown,
+        }
+
+        impl Display for RedoseStatus {
+            fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+                f.write_str(match self {
+                    RedoseStatus::TooEarly => "too early",
+                    RedoseStatus::Active => "active",
+                    RedoseStatus::Clear => "clear",
+                    RedoseStatus::Unknown => "unknown",
+                })
+            }
+        }
+
+        /// Active amount is sum of doses in unit of last dose taken within duration of
+        /// substance, or within last day when duration is unknown.
+        #[derive(Tabled, Serialize, Debug, Clone, PartialEq)]
+        pub(crate) struct RedoseGuidance {
+            pub substance: String,
+            pub last_dose: String,
+            pub since_last_dose: String,
+            pub active_amount: f64,
+            pub unit: String,
+            #[tabled(display_with = "display_option")]
+            pub duration: Option<String>,
+            #[tabled(display_with = "display_option")]
+            pub redose_interval: Option<String>,
+            #[tabled(display_with = "display_option")]
+            pub active_for: Option<String>,
+            #[tabled(display_with = "display_option")]
+            pub wait: Option<String>,
+            pub status: RedoseStatus,
+        }
+
+        pub(crate) fn redose_guidance(
+            substance: &sea_entity::substance::Model,
+            doses: &[Dose],
+            now: NaiveDateTime,
+        ) -> Option<RedoseGuidance> {
+            let time_of = |dose: &Dose| dose.date.and_time(dose.time);
+            let last_dose = doses.iter().max_by_key(|dose| time_of(dose))?;
+            let since_last_dose = now - time_of(last_dose);
+
+            let minutes = |minutes: Option<i32>| minutes.map(|minutes| TimeDelta::minutes(minutes.into()));
+            let duration = minutes(substance.duration_minutes);
+            let redose_interval = minutes(substance.redose_interval_minutes);
+
+            let window = duration.unwrap_or(TimeDelta::days(1));
+            let active_amount = doses
+                .iter()
+                .filter(|dose| dose.unit == last_dose.unit && now - time_of(dose) < window)
+                .fold(0.0, |total, dose| total + dose.amount);
+
+            let remaining = |period: Option<TimeDelta>| {
+                period
+                    .filter(|period| *period > since_last_dose)
+                    .map(|period| period - since_last_dose)
+            };
+            let active_for = remaining(duration);
+            let wait = remaining(redose_interval);
+
+            let status = match (duration, redose_interval) {
+                (None, None) => RedoseStatus::Unknown,
+                _ if wait.is_some() => RedoseStatus::TooEarly,
+                _ if active_for.is_some() => RedoseStatus::Active,
+                _ => RedoseStatus::Clear,
+            };
+
+            Some(RedoseGuidance {
+                substance: substance.name.clone(),
+                last_dose: time_of(last_dose).format("%Y-%m-%d %H:%M").to_string(),
+                since_last_dose: format_duration(since_last_dose),
+                active_amount: round(active_amount),
+                unit: last_dose.unit.clone(),
+                duration: duration.map(format_duration),
+                redose_interval: redose_interval.map(format_duration),
+                active_for: active_for.map(format_duration),
+                wait: wait.map(format_duration),
+                status,
+            })
+        }
+
+        pub async fn execute_redose_command(redose: Redose, database_connection: &DatabaseConnection) -> Result<()> {
+            let substance = find_substance(&redose.substance, database_connection).await?;
+            let filter = IngestionFilter {
+                substance: Some(substance.id.clone()),
+                ..Default::default()
+            };
+            let doses = load_doses(&filter, database_connection).await?;
+
+            let guidance = match redose_guidance(&substance, &doses, Local::now().naive_local()) {
+                Some(guidance) => guidance,
+                None => {
+                    output::print_message(format!("There are no ingestions of {} yet.", substance.name));
+                    return Ok(());
+                }
+            };
+            output::print_record(&guidance)?;
+
+            output::print_message(match guidance.status {
+                RedoseStatus::TooEarly => format!(
+                    "Minimum redose interval of {} did not pass yet, wait {}.",
+                    guidance.substance,
+                    display_option(&guidance.wait)
+                ),
+                RedoseStatus::Active => format!(
+                    "{} is still active for {}, redose would stack with {} {} taken within its duration.",
+                    guidance.substance,
+                    display_option(&guidance.active_for),
+                    guidance.active_amount,
+                    guidance.unit
+                ),
+                RedoseStatus::Clear => format!("Previous doses of {} are no longer active.", guidance.substance),
+                RedoseStatus::Unknown => format!(
+                    "Duration of {} is unknown, set it with `substance update --id {} --duration 4h --redose-interval 2h`.",
+                    guidance.substance, substance.alias
+                ),
+            });
+
+            Ok(())
+        }
+    }
+
+    pub(super) mod tui {
+        use std::collections::BTreeMap;
+        use std::io::{stdout, IsTerminal, Stdout};
+
+        use chrono::{DateTime, Local, NaiveDate};
+        use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+        use crossterm::execute;
+        use crossterm::terminal::{
+            disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+        };
+        use ratatui::backend::{Backend, CrosstermBackend};
+        use ratatui::layout::{Constraint, Layout, Rect};
+        use ratatui::style::{Color, Modifier, Style};
+        use ratatui::text::{Line, Span};
+        use ratatui::widgets::{
+            Block, Borders, List, ListItem, ListState, Paragraph, Row, Table, TableState, Tabs,
+        };
+        use ratatui::{Frame, Terminal};
+        use sea_orm::{DatabaseConnection, EntityTrait, QueryOrder};
+
+        use crate::cli::ingestion::{
+            create_ingestion, find_ingestions, CreateIngestion, Ingestion, IngestionFilter,
+        };
+        use crate::cli::limits::{describe_warning, list_limits, LimitStatus, LimitUsage};
+        use crate::cli::stats::{load_doses, round, Dose};
+        use crate::config;
+        use crate::error::{Error, Result};
+
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub(crate) enum View {
+            Today,
+            Timeline,
+            Substances,
+            Log,
+        }
+
+        impl View {
+            const ALL: [View; 4] = [View::Today, View::Timeline, View::Substances, View::Log];
+
+            fn title(self) -> &'static str {
+                match self {
+                    View::Today => "Today",
+                    View::Timeline => "Timeline",
+                    View::Substances => "Substances",
+                    View::Log => "Log",
+                }
+            }
+
+            fn index(self) -> usize {
+                View::ALL
+                    .iter()
+                    .position(|view| *view == self)
+                    .unwrap_or_default()
+            }
+
+            fn cycle(self, step: usize) -> View {
+                View::ALL[(self.index() + step) % View::ALL.len()]
+            }
+        }
+
+        /// Fields of quick-log form in order of their inputs.
+        const FIELDS: [&str; 5] = ["Substance", "Amount", "Unit", "Route", "Notes"];
+        const SUBSTANCE: usize = 0;
+
+        #[derive(Debug, PartialEq, Eq)]
+        pub(crate) enum Action {
+            Continue,
+            Submit,
+            Quit,
+        }
+
+        /// Consumption of one substance in one unit during today.
+        #[derive(Debug, Clone, PartialEq)]
+        pub(crate) struct TodayTotal {
+            pub substance: String,
+            pub unit: String,
+            pub amount: f64,
+            pub doses: usize,
+            pub last: String,
+        }
+
+        pub(crate) fn today_totals(doses: &[Dose], today: NaiveDate) -> Vec<TodayTotal> {
+            let mut totals: BTreeMap<(String, String), TodayTotal> = BTreeMap::new();
+
+            for dose in doses.iter().filter(|dose| dose.date == today) {
+                let total = totals
+                    .entry((dose.substance.clone(), dose.unit.clone()))
+                    .or_insert_with(|| TodayTotal {
+                        substance: dose.substance.clone(),
+                        unit: dose.unit.clone(),
+                        amount: 0.0,
+                        doses: 0,
+                        last: String::new(),
+                    });
+                total.amount = round(total.amount + dose.amount);
+                total.doses += 1;
+                total.last = total.last.clone().max(dose.time.format("%H:%M").to_string());
+            }
+
+            totals.into_values().collect()
+        }
+
+        /// State of terminal interface, kept apart from drawing so key handling can be tested
+        /// without terminal.
+        #[derive(Default)]
+        pub(crate) struct App {
+            pub view: Option<View>,
+            /// Ingestions from the newest one.
+            pub ingestions: Vec<Ingestion>,
+            pub substances: Vec<sea_entity::substance::Model>,
+            pub today: Vec<TodayTotal>,
+            pub limits: Vec<LimitUsage>,
+            pub selected_ingestion: usize,
+            pub selected_substance: usize,
+            pub search: String,
+            pub searching: bool,
+            pub field: usize,
+            pub form: [String; 5],
+            pub status: Option<String>,
+        }
+
+        impl App {
+            pub(crate) fn view(&self) -> View {
+                self.view.unwrap_or(View::Today)
+            }
+
+            async fn reload(&mut self, database_connection: &DatabaseConnection) -> Result<()> {
+                self.ingestions = find_ingestions(&IngestionFilter::default(), database_connection)
+                    .await?
+                    .into_iter()
+                    .rev()
+                    .map(|(ingestion, substance)| Ingestion::new(ingestion, substance))
+                    .collect();
+
+                self.substances = sea_entity::substance::Entity::find()
+                    .order_by_asc(sea_entity::substance::Column::Name)
+                    .all(database_connection)
+                    .await?;
+
+                let today = Local::now().date_naive();
+                let filter = IngestionFilter {
+                    since: today
+                        .and_hms_opt(0, 0, 0)
+                        .and_then(|midnight| midnight.and_local_timezone(Local).earliest()),
+                    ..Default::default()
+                };
+                self.today = today_totals(&load_doses(&filter, database_connection).await?, today);
+                self.limits = list_limits(database_connection).await?;
+
+                self.selected_ingestion = self
+                    .selected_ingestion
+                    .min(self.ingestions.len().saturating_sub(1));
+                Ok(())
+            }
+
+            /// Substances with name containing searched text, compared case-insensitively.
+            pub(crate) fn filtered_substances(&self) -> Vec<&sea_entity::substance::Model> {
+                let search = self.search.trim().to_lowercase();
+                self.substances
+                    .iter()
+                    .filter(|substance| substance.name.to_lowercase().contains(&search))
+                    .collect()
+            }
+
+            /// First substance which name starts with typed substance, if it is not typed whole already.
+            pub(crate) fn completion(&self) -> Option<&str> {
+                let typed = self.form[SUBSTANCE].trim().to_lowercase();
+                if typed.is_empty() {
+                    return None;
+                }
+
+                self.substances
+                    .iter()
+                    .map(|substance| substance.name.as_str())
+                    .find(|name| name.to_lowercase().starts_with(&typed) && name.to_lowercase() != typed)
+            }
+
+            pub(crate) fn ingestion_command(&self) -> Result<CreateIngestion> {
+                let value =
+                    |field: usize| Some(self.form[field].trim().to_owned()).filter(|value| !value.is_empty());
+
+                let substance_id =
+                    value(SUBSTANCE).ok_or_else(|| Error::InvalidInput("Substance is required".to_owned()))?;
+                let dosage_amount = value(1)
+                    .map(|amount| {
+                        amount
+                            .parse::<f64>()
+                            .map_err(|_| Error::InvalidDose(format!("`{}` is not an amount", amount)))
+                    })
+                    .transpose()?;
+
+                Ok(CreateIngestion {
+                    substance_id,
+                    dosage_unit: value(2),
+                    dosage_amount,
+                    ingestion_date: Local::now(),
+                    route_of_administration: value(3),
+                    notes: value(4),
+                    force: false,
+                })
+            }
+
+            fn open_view(&mut self, view: View) {
+                self.view = Some(view);
+                self.searching = false;
+            }
+
+            pub(crate) fn handle_key(&mut self, key: KeyEvent) -> Action {
+                if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+                    return Action::Quit;
+                }
+
+                match self.view() {
+                    View::Log => self.handle_form_key(key),
+                    View::Substances if self.searching => {
+                        match key.code {
+                            KeyCode::Char(character) => self.search.push(character),
+                            KeyCode::Backspace => {
+                                self.search.pop();
+                            }
+                            KeyCode::Esc => {
+                                self.search.clear();
+                                self.searching = false;
+                            }
+                            KeyCode::Enter => self.searching = false,
+                            _ => {}
+                        }
+                        self.selected_substance = 0;
+                        Action::Continue
+                    }
+                    view => {
+                        match key.code {
+                            KeyCode::Char('q') | KeyCode::Esc => return Action::Quit,
+                            KeyCode::Tab | KeyCode::Right => self.open_view(view.cycle(1)),
+                            KeyCode::BackTab | KeyCode::Left => self.open_view(view.cycle(View::ALL.len() - 1)),
+                            KeyCode::Char(digit @ '1'..='4') => {
+                                self.open_view(View::ALL[digit as usize - '1' as usize])
+                            }
+                            KeyCode::Char('a') => self.open_view(View::Log),
+                            KeyCode::Char('/') if view == View::Substances => self.searching = true,
+                            KeyCode::Enter if view == View::Substances => {
+                                if let Some(substance) = self.filtered_substances().get(self.selected_substance) {
+                                    self.form[SUBSTANCE] = substance.name.clone();
+                                    self.field = 1;
+                                    self.open_view(View::Log);
+                                }
+                            }
+                            code => self.scroll(view, code),
+                        }
+                        Action::Continue
+                    }
+                }
+            }
+
+            fn scroll(&mut self, view: View, code: KeyCode) {
+                let (selected, length) = match view {
+                    View::Timeline => (&mut self.selected_ingestion, self.ingestions.len()),
+                    View::Substances => {
+                        let length = self.filtered_substances().len();
+                        (&mut self.selected_substance, length)
+                    }
+                    _ => return,
+                };
+
+                let last = length.saturating_sub(1);
+                *selected = match code {
+                    KeyCode::Down | KeyCode::Char('j') => (*selected + 1).min(last),
+                    KeyCode::Up | KeyCode::Char('k') => selected.saturating_sub(1),
+                    KeyCode::PageDown => (*selected + 10).min(last),
+                    KeyCode::PageUp => selected.saturating_sub(10),
+                    KeyCode::Home | KeyCode::Char('g') => 0,
+                    KeyCode::End | KeyCode::Char('G') => last,
+                    _ => *selected,
+                };
+            }
+
+            fn handle_form_key(&mut self, key: KeyEvent) -> Action {
+                match key.code {
+                    KeyCode::Esc => self.open_view(View::Today),
+                    KeyCode::Enter => return Action::Submit,
+                    KeyCode::Tab => match self.completion().map(str::to_owned) {
+                        Some(name) if self.field == SUBSTANCE => self.form[SUBSTANCE] = name,
+                        _ => self.field = (self.field + 1) % FIELDS.len(),
+                    },
+                    KeyCode::Down => self.field = (self.field + 1) % FIELDS.len(),
+                    KeyCode::BackTab | KeyCode::Up => self.field = (self.field + FIELDS.len() - 1) % FIELDS.len(),
+                    KeyCode::Backspace => {
+                        self.form[self.field].pop();
+                    }
+                    KeyCode::Char(character) => self.form[self.field].push(character),
+                    _ => {}
+                }
+                Action::Continue
+            }
+
+            async fn submit(&mut self, database_connection: &DatabaseConnection) -> Result<()> {
+                let command = self.ingestion_command()?;
+                let (ingestion, warnings) = create_ingestion(command, database_connection).await?;
+
+                self.status = Some(match warnings.first() {
+                    Some(usage) => describe_warning(usage),
+                    None => format!(
+                        "Logged {} {} of {}",
+                        ingestion.dosage_value,
+                        ingestion.dosage_unit,
+                        self.form[SUBSTANCE].trim()
+                    ),
+                });
+                self.form = Default::default();
+                self.field = SUBSTANCE;
+                self.reload(database_connection).await
+            }
+        }
+
+        fn block(title: &str) -> Block<'_> {
+            Block::default().borders(Borders::ALL).title(title)
+        }
+
+        fn highlight() -> Style {
+            Style::default().add_modifier(Modifier::REVERSED)
+        }
+
+        fn draw_today(frame: &mut Frame, app: &App, area: Rect) {
+            let [totals_area, limits_area] =
+                Layout::vertical([Constraint::Percentage(60), Constraint::Percentage(40)]).areas(area);
+
+            let totals = app.today.iter().map(|total| {
+                Row::new(vec![
+                    total.substance.clone(),
+                    format!("{} {}", total.amount, total.unit),
+                    total.doses.to_string(),
+                    total.last.clone(),
+                ])
+            });
+            let totals = Table::new(
+                totals,
+                [
+                    Constraint::Fill(2),
+                    Constraint::Fill(1),
+                    Constraint::Length(6),
+                    Constraint::Length(6),
+                ],
+            )
+            .header(
+                Row::new(vec!["Substance", "Total", "Doses", "Last"])
+                    .style(Style::default().add_modifier(Modifier::BOLD)),
+            )
+            .block(block("Today"));
+            frame.render_widget(totals, totals_area);
+
+            let limits = app.limits.iter().map(|usage| {
+                let color = match usage.status {
+                    LimitStatus::Ok => Color::Green,
+                    LimitStatus::Approaching => Color::Yellow,
+                    LimitStatus::Exceeded => Color::Red,
+                };
+                Row::new(vec![
+                    usage.substance.clone(),
+                    usage.window.clone(),
+                    format!("{} / {} {}", usage.used, usage.maximum, usage.unit),
+                    format!("{}%", usage.percent),
+                ])
+                .style(Style::default().fg(color))
+            });
+            let limits = Table::new(
+                limits,
+                [
+                    Constraint::Fill(2),
+                    Constraint::Fill(1),
+                    Constraint::Fill(2),
+                    Constraint::Length(8),
+                ],
+            )
+            .header(
+                Row::new(vec!["Substance", "Window", "Used", "Percent"])
+                    .style(Style::default().add_modifier(Modifier::BOLD)),
+            )
+            .block(block("Limits"));
+            frame.render_widget(limits, limits_area);
+        }
+
+        fn draw_timeline(frame: &mut Frame, app: &App, area: Rect) {
+            let rows = app.ingestions.iter().map(|ingestion| {
+                let ingested_at = DateTime::parse_from_rfc3339(&ingestion.ingested_at)
+                    .map(|date| date.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or_else(|_| ingestion.ingested_at.clone());
+                Row::new(vec![
+                    ingestion.alias.to_string(),
+                    ingested_at,
+                    ingestion.substance.clone(),
+                    format!("{} {}", ingestion.dosage, ingestion.unit),
+                    ingestion.route.clone().unwrap_or_default(),
+                    ingestion.notes.clone().unwrap_or_default(),
+                ])
+            });
+            let widths = [
+                Constraint::Length(6),
+                Constraint::Length(17),
+                Constraint::Fill(1),
+                Constraint::Fill(1),
+                Constraint::Fill(1),
+                Constraint::Fill(2),
+            ];
+            let table = Table::new(rows, widths)
+                .header(
+                    Row::new(vec![
+                        "Alias",
+                        "Ingested at",
+                        "Substance",
+                        "Dosage",
+                        "Route",
+                        "Notes",
+                    ])
+                    .style(Style::default().add_modifier(Modifier::BOLD)),
+                )
+                .block(block("Timeline"))
+                .highlight_style(highlight());
+
+            let mut state = TableState::default().with_selected(Some(app.selected_ingestion));
+            frame.render_stateful_widget(table, area, &mut state);
+        }
+
+        fn draw_substances(frame: &mut Frame, app: &App, area: Rect) {
+            let [search_area, list_area] =
+                Layout::vertical([Constraint::Length(3), Constraint::Min(0)]).areas(area);
+
+            let search = Paragraph::new(app.search.as_str()).block(block(if app.searching {
+                "Search (Enter to finish, Esc to clear)"
+            } else {
+                "Search (/)"
+            }));
+            frame.render_widget(search, search_area);
+            if app.searching {
+                frame.set_cursor(
+                    search_area.x + 1 + app.search.chars().count() as u16,
+                    search_area.y + 1,
+                );
+            }
+
+            let items: Vec<ListItem> = app
+                .filtered_substances()
+                .iter()
+                .map(|substance| ListItem::new(format!("{:>4}  {}", substance.alias, substance.name)))
+                .collect();
+            let list = List::new(items)
+                .block(block("Substances"))
+                .highlight_style(highlight());
+
+            let mut state = ListState::default().with_selected(Some(app.selected_substance));
+            frame.render_stateful_widget(list, list_area, &mut state);
+        }
+
+        fn draw_form(frame: &mut Frame, app: &App, area: Rect) {
+            let dosage_unit = &config::get().dosage_unit;
+
+            let lines: Vec<Line> = FIELDS
+                .iter()
+                .enumerate()
+                .map(|(field, label)| {
+                    let mut spans = vec![
+                        Span::styled(
+                            format!("{:>10}: ", label),
+                            Style::default().add_modifier(Modifier::BOLD),
+                        ),
+                        Span::raw(app.form[field].clone()),
+                    ];
+                    let hint = match field {
+                        SUBSTANCE if field == app.field => app.completion().map(|name| {
+                            name.chars()
+                                .skip(app.form[field].trim().chars().count())
+                                .collect()
+                        }),
+                        2 if app.form[field].is_empty() => Some(dosage_unit.clone()),
+                        _ => None,
+                    };
+                    if let Some(hint) = hint {
+                        spans.push(Span::styled(hint, Style::default().fg(Color::DarkGray)));
+                    }
+
+                    match field == app.field {
+                        true => Line::from(spans).style(Style::default().fg(Color::Cyan)),
+                        false => Line::from(spans),
+                    }
+                })
+                .collect();
+
+            frame.render_widget(Paragraph::new(lines).block(block("Log ingestion")), area);
+            frame.set_cursor(
+                area.x + 13 + app.form[app.field].chars().count() as u16,
+                area.y + 1 + app.field as u16,
+            );
+        }
+
+        fn draw(frame: &mut Frame, app: &App) {
+            let [tabs_area, body_area, status_area] =
+                Layout::vertical([Constraint::Length(3), Constraint::Min(0), Constraint::Length(1)])
+                    .areas(frame.size());
+
+            let tabs = Tabs::new(
+                View::ALL
+                    .iter()
+                    .enumerate()
+                    .map(|(index, view)| format!("{} {}", index + 1, view.title())),
+            )
+            .select(app.view().index())
+            .highlight_style(highlight())
+            .block(block("neuronek"));
+            frame.render_widget(tabs, tabs_area);
+
+            match app.view() {
+                View::Today => draw_today(frame, app, body_area),
+                View::Timeline => draw_timeline(frame, app, body_area),
+                View::Substances => draw_substances(frame, app, body_area),
+                View::Log => draw_form(frame, app, body_area),
+            }
+
+            let help = match app.view() {
+                View::Log => "Enter log  Tab complete/next  ↑↓ field  Esc back",
+                View::Substances => "/ search  Enter log substance  ↑↓ select  Tab view  q quit",
+                _ => "Tab view  ↑↓ scroll  a log  q quit",
+            };
+            let status = app.status.as_deref().unwrap_or(help);
+            frame.render_widget(
+                Paragraph::new(status).style(Style::default().fg(Color::DarkGray)),
+                status_area,
+            );
+        }
+
+        async fn run<B: Backend>(
+            terminal: &mut Terminal<B>,
+            app: &mut App,
+            database_connection: &DatabaseConnection,
+        ) -> Result<()> {
+            loop {
+                terminal
+                    .draw(|frame| draw(frame, app))
+                    .map_err(Error::io("Failed to draw terminal interface"))?;
+
+                let key = match event::read().map_err(Error::io("Failed to read terminal event"))? {
+                    Event::Key(key) if key.kind == KeyEventKind::Press => key,
+                    _ => continue,
+                };
+
+                app.status = None;
+                match app.handle_key(key) {
+                    Action::Quit => return Ok(()),
+                    Action::Submit => {
+                        if let Err(error) = app.submit(database_connection).await {
+                            app.status = Some(error.to_string());
+                        }
+                    }
+                    Action::Continue => {}
+                }
+            }
+        }
+
+        fn restore_terminal(terminal: &mut Terminal<CrosstermBackend<Stdout>>) -> Result<()> {
+            disable_raw_mode().map_err(Error::io("Failed to disable raw mode of terminal"))?;
+            execute!(terminal.backend_mut(), LeaveAlternateScreen)
+                .map_err(Error::io("Failed to leave alternate screen"))?;
+            terminal.show_cursor().map_err(Error::io("Failed to show cursor"))
+        }
+
+        pub async fn execute_tui_command(database_connection: &DatabaseConnection) -> Result<()> {
+            if !stdout().is_terminal() {
+                return Err(Error::InvalidOperation(
+                    "Terminal interface can be used only in interactive terminal".to_owned(),
+                ));
+            }
+
+            let mut app = App::default();
+            app.reload(database_connection).await?;
+
+            enable_raw_mode().map_err(Error::io("Failed to enable raw mode of terminal"))?;
+            execute!(stdout(), EnterAlternateScreen).map_err(Error::io("Failed to enter alternate screen"))?;
+            let mut terminal = Terminal::new(CrosstermBackend::new(stdout()))
+                .map_err(Error::io("Failed to initialize terminal"))?;
+
+            let result = run(&mut terminal, &mut app, database_connection).await;
+            restore_terminal(&mut terminal)?;
+            result
+        }
+    }
+
+    pub(super) mod completions {
+        use std::ffi::{OsStr, OsString};
+        use std::io::{stdout, Write};
+        use std::path::PathBuf;
+
+        use async_std::task;
+        use chrono::{DateTime, Local};
+        use clap::{CommandFactory, Parser, ValueEnum};
+        use clap_complete::engine::CompletionCandidate;
+        use clap_complete::env::EnvCompleter;
+        use sea_orm::sea_query::Expr;
+        use sea_orm::{DatabaseConnection, EntityTrait, QueryOrder, QuerySelect};
+
+        use crate::cli::streaks::format_duration;
+        use crate::cli::Program;
+        use crate::error::{Error, Result};
+        use crate::{config, crypto, db};
+
+        /// Environment variable which makes program complete command line instead of running it.
+        pub(crate) const COMPLETE_VARIABLE: &str = "COMPLETE";
+
+        /// Number of the latest ingestions offered as recent doses.
+        const RECENT_INGESTIONS: u64 = 100;
+        const RECENT_DOSES: usize = 10;
+
+        #[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum CompletionShell {
+            Bash,
+            Zsh,
+            Fish,
+        }
+
+        impl CompletionShell {
+            fn completer(self) -> &'static dyn EnvCompleter {
+                match self {
+                    CompletionShell::Bash => &clap_complete::env::Bash,
+                    CompletionShell::Zsh => &clap_complete::env::Zsh,
+                    CompletionShell::Fish => &clap_complete::env::Fish,
+                }
+            }
+
+            fn generator(self) -> clap_complete::aot::Shell {
+                match self {
+                    CompletionShell::Bash => clap_complete::aot::Shell::Bash,
+                    CompletionShell::Zsh => clap_complete::aot::Shell::Zsh,
+                    CompletionShell::Fish => clap_complete::aot::Shell::Fish,
+                }
+            }
+        }
+
+        #[derive(Parser, Debug)]
+        #[command(version, about, long_about = None)]
+        pub struct Completions {
+            pub shell: CompletionShell,
+            /// Generate script which completes only commands and flags, without calling program
+            /// for names of substances and recent doses
+            #[arg(long = "static")]
+            pub static_script: bool,
+        }
+
+        /// Name of executable as it was invoked, completions are registered for this name.
+        fn executable() -> Result<(String, String)> {
+            let invoked = PathBuf::from(std::env::args_os().next().unwrap_or_default());
+            let bin = invoked
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| Program::command().get_name().to_owned());
+
+            // Bare name is looked up in `PATH`, relative paths would break outside of current directory.
+            let completer = match invoked.components().count() {
+                1 => bin.clone(),
+                _ => std::env::current_exe()
+                    .map_err(Error::io("Failed to locate executable"))?
+                    .to_string_lossy()
+                    .into_owned(),
+            };
+
+            Ok((bin, completer))
+        }
+
+        /// Script of given shell, generated scripts are written whole so closed pipe does not panic.
+        pub fn write_completions(completions: Completions) -> Result<Vec<u8>> {
+            let (bin, completer) = executable()?;
+            let mut command = Program::command();
+            let mut buffer = Vec::new();
+
+            match completions.static_script {
+                true => clap_complete::aot::generate(completions.shell.generator(), &mut command, bin, &mut buffer),
+                false => completions
+                    .shell
+                    .completer()
+                    .write_registration(COMPLETE_VARIABLE, command.get_name(), &bin, &completer, &mut buffer)
+                    .map_err(Error::io("Failed to write completions"))?,
+            }
+
+            Ok(buffer)
+        }
+
+        pub fn execute_completions_command(completions: Completions) -> Result<()> {
+            stdout()
+                .write_all(&write_completions(completions)?)
+                .map_err(Error::io("Failed to write completions"))
+        }
+
+        /// Ingested dose of substance, newest doses are first.
+        pub(crate) struct RecentDose {
+            pub substance: String,
+            pub amount: f64,
+            pub unit: String,
+            pub ingested_at: DateTime<Local>,
+        }
+
+        pub(crate) async fn query_journal(
+            database_connection: &DatabaseConnection,
+        ) -> Result<(Vec<sea_entity::substance::Model>, Vec<RecentDose>)> {
+            let substances = sea_entity::substance::Entity::find()
+                .order_by_asc(sea_entity::substance::Column::Name)
+                .all(database_connection)
+                .await?;
+
+            let doses = sea_entity::ingestion::Entity::find()
+                .find_also_related(sea_entity::substance::Entity)
+                .order_by_desc(db::julian_day(Expr::col(sea_entity::ingestion::Column::IngestedAt)))
+                .limit(RECENT_INGESTIONS)
+                .all(database_connection)
+                .await?
+                .into_iter()
+                .filter_map(|(ingestion, substance)| {
+                    Some(RecentDose {
+                        substance: substance?.name,
+                        amount: ingestion.dosage_value,
+                        unit: ingestion.dosage_unit,
+                        ingested_at: ingestion.ingested_at.with_timezone(&Local),
+                    })
+                })
+                .collect();
+
+            Ok((substances, doses))
+        }
+
+        /// Options choosing journal which were given on command line being completed.
+        #[derive(Debug, Default, PartialEq)]
+        pub(crate) struct JournalOptions {
+            pub config: Option<PathBuf>,
+            pub database: Option<PathBuf>,
+            pub profile: Option<String>,
+        }
+
+        /// Finds options choosing journal in words of command line, which can't be
+        /// parsed by clap as it's incomplete, unrelated words are skipped.
+        pub(crate) fn journal_options(words: impl IntoIterator<Item = OsString>) -> JournalOptions {
+            let (mut config, mut database, mut profile) = (None, None, None);
+            let mut words = words.into_iter().map(|word| word.to_string_lossy().into_owned());
+
+            while let Some(word) = words.next() {
+                let (name, attached) = match word.split_once('=') {
+                    Some((name, value)) if name.starts_with("--") => (name, Some(value)),
+                    _ if !word.starts_with("--") && word.len() > 2 && word.is_char_boundary(2) => {
+                        let (name, value) = word.split_at(2);
+                        (name, Some(value))
+                    }
+                    _ => (word.as_str(), None),
+                };
+
+                let option = match name {
+                    "-c" | "--config" => &mut config,
+                    "--database" => &mut database,
+                    "-p" | "--profile" => &mut profile,
+                    _ => continue,
+                };
+                *option = attached.map(str::to_owned).or_else(|| words.next());
+            }
+
+            JournalOptions {
+                config: config.map(PathBuf::from),
+                database: database.map(PathBuf::from),
+                profile,
+            }
+        }
+
+        /// Words of command line being completed, shells pass them after `--`.
+        fn completed_words() -> Vec<OsString> {
+            let mut arguments = std::env::args_os().skip_while(|argument| argument != "--");
+            arguments.next();
+            arguments.skip(1).collect()
+        }
+
+        /// Reads journal chosen on command line or by configuration in read-only mode, encrypted
+        /// journal is skipped unless passphrase is in environment as completions cannot prompt for it.
+        async fn load_journal() -> Result<(Vec<sea_entity::substance::Model>, Vec<RecentDose>)> {
+            let options = journal_options(completed_words());
+            config::initialize(
+                options.config.as_deref(),
+                config::Arguments {
+                    database_path: options.database.as_deref(),
+                    profile: options.profile.as_deref(),
+                    output_format: None,
+                },
+            )?;
+            let path = db::get_database_path()?;
+
+            if !path.exists()
+                || (db::is_database_encrypted(&path) && std::env::var_os(crypto::PASSPHRASE_VARIABLE).is_none())
+            {
+                return Ok(Default::default());
+            }
+
+            let database_connection = db::open_database(&path, true).await?;
+            let journal = query_journal(&database_connection).await;
+            database_connection.close().await?;
+            journal
+        }
+
+        fn describe(dose: &RecentDose, now: DateTime<Local>) -> String {
+            format!(
+                "{} {} {} ago",
+                dose.amount,
+                dose.unit,
+                format_duration(now - dose.ingested_at)
+            )
+        }
+
+        pub(crate) fn substance_candidates(
+            current: &str,
+            substances: &[sea_entity::substance::Model],
+            doses: &[RecentDose],
+            now: DateTime<Local>,
+        ) -> Vec<CompletionCandidate> {
+            let current = current.to_lowercase();
+
+            substances
+                .iter()
+                .filter(|substance| substance.name.to_lowercase().starts_with(&current))
+                .map(|substance| {
+                    let last_dose = doses.iter().find(|dose| dose.substance == substance.name);
+                    CompletionCandidate::new(&substance.name)
+                        .help(last_dose.map(|dose| format!("last {}", describe(dose, now)).into()))
+                })
+                .collect()
+        }
+
+        pub(crate) fn dose_candidates(
+            current: &str,
+            doses: &[RecentDose],
+            now: DateTime<Local>,
+        ) -> Vec<CompletionCandidate> {
+            let mut amounts: Vec<String> = Vec::new();
+
+            doses
+                .iter()
+                .filter(|dose| {
+                    let amount = dose.amount.to_string();
+                    let recent = amount.starts_with(current) && !amounts.contains(&amount);
+                    if recent {
+                        amounts.push(amount);
+                    }
+                    recent
+                })
+                .take(RECENT_DOSES)
+                .map(|dose| {
+                    CompletionCandidate::new(dose.amount.to_string()).help(Some(
+                        format!("{} {}", dose.substance, describe(dose, now)).into(),
+                    ))
+                })
+                .collect()
+        }
+
+        /// Completes names of substances from journal, each described by its last dose.
+        pub(crate) fn complete_substances(current: &OsStr) -> Vec<CompletionCandidate> {
+            let (substances, doses) = task::block_on(load_journal()).unwrap_or_default();
+            substance_candidates(&current.to_string_lossy(), &substances, &doses, Local::now())
+        }
+
+        /// Completes amounts of recent doses, the newest ones first.
+        pub(crate) fn complete_doses(current: &OsStr) -> Vec<CompletionCandidate> {
+            let (_, doses) = task::block_on(load_journal()).unwrap_or_default();
+            dose_candidates(&current.to_string_lossy(), &doses, Local::now())
+        }
+    }
+
+    #[derive(Subcommand)]
+    pub(super) enum ConfigCommands {
+        /// Print effective configuration and where each value comes from
+        Show,
+    }
+
+    #[derive(Parser)]
+    #[command(args_conflicts_with_subcommands = true)]
+    pub(super) struct ConfigCommand {
+        #[command(subcommand)]
+        pub command: ConfigCommands,
+    }
+
+    #[derive(Subcommand)]
+    pub(super) enum ProgramCommand {
+        #[command(flatten)]
+        Journal(Box<JournalCommand>),
+        /// Inspect configuration
+        Config(ConfigCommand),
+        /// Print shell completions which complete names of substances and recent doses
+        Completions(completions::Completions),
+    }
+
+    /// Commands which are run with configuration loaded, unlike the rest of them.
+    #[derive(Subcommand)]
+    pub(super) enum JournalCommand {
+        Substance(substance::SubstanceCommand),
+        Ingestion(ingestion::IngestionCommand),
+        #[command(name = "db")]
+        Database(database::DatabaseCommand),
+        Export(export::ExportCommand),
+        Import(import::ImportCommand),
+        /// Create consistent snapshot of journal database
+        Backup(backup::Backup),
+        /// Replace journal database with a backup
+        Restore(backup::Restore),
+        /// Manage profiles, each profile has its own journal
+        Profile(profile::ProfileCommand),
+        /// Summarize consumption of substances per day, week or month
+        Stats(stats::Stats),
+        /// Draw sparklines, bar charts or calendar heatmap of ingestions
+        Chart(chart::Chart),
+        /// Show and manage daily, weekly or monthly limits of substances
+        Limits(limits::LimitsCommand),
+        /// Show abstinence streaks and relapses of substances
+        Streaks(streaks::Streaks),
+        /// Show time since last dose and whether substance is still active before redosing
+        Redose(redose::Redose),
+        /// Browse journal and log ingestions in full-screen terminal interface
+        Tui,
+        /// Log the last ingestion (of given substance) again with current time
+        Again(ingestion::Again),
+    }
+
+    #[derive(Parser)]
+    #[command(
+        version = "0.0.1-dev",
+        about = "Dosage journal that knows!",
+        long_about = "🧬 Intelligent dosage tracker application with purpose to monitor supplements, nootropics and psychoactive substances along with their long-term influence on one's mind and body."
+    )]
+    pub(super) struct Program {
+        /// Substance to summarize, without it the latest ingestions are shown
+        #[arg(add = ArgValueCompleter::new(completions::complete_substances))]
+        pub name: Option<String>,
+
+        /// Sets a custom config file
+        #[arg(short, long, value_name = "FILE")]
+        pub config: Option<PathBuf>,
+
+        /// Journal database file or `sqlite://` URL, overrides `DATABASE_URL` and configuration
+        #[arg(long, value_name = "FILE")]
+        pub database: Option<PathBuf>,
+
+        /// Profile which journal should be used
+        #[arg(short, long, value_name = "NAME")]
+        pub profile: Option<String>,
+
+        /// Format of results, overrides `output_format` from configuration
+        #[arg(short, long, value_name = "FORMAT")]
+        pub output: Option<OutputFormat>,
+
+        /// Log progress to standard error, repeat for more detail (`-dd` debug, `-ddd` SQL statements)
+        #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+        pub debug: u8,
+
+        #[command(subcommand)]
+        pub command: Option<ProgramCommand>,
+    }
+
+    pub(super) async fn run_program() -> Result<()> {
+        let cli = Program::parse();
+
+        let arguments = config::Arguments {
+            database_path: cli.database.as_deref(),
+            profile: cli.profile.as_deref(),
+            output_format: cli.output,
+        };
+
+        if let (Some(name), Some(_)) = (&cli.name, &cli.command) {
+            return Err(Error::InvalidInput(format!(
+                "Substance {} cannot be combined with command, use `--substance` option of command instead",
+                name
+            )));
+        }
+
+        let command = match cli.command {
+            Some(ProgramCommand::Completions(completions_command)) => {
+                return completions::execute_completions_command(completions_command);
+            }
+            Some(ProgramCommand::Config(config_command)) => {
+                return match config_command.command {
+                    ConfigCommands::Show => config::show_configuration(cli.config.as_deref(), arguments),
+                };
+            }
+            Some(ProgramCommand::Journal(command)) => Some(*command),
+            None => None,
+        };
+
+        let configuration = config::initialize(cli.config.as_deref(), arguments)?;
+        logging::initialize(cli.debug, configuration.log_file)?;
+
+        // Bare `nurk` lists the latest ingestions and `nurk <substance>` summarizes substance.
+        let Some(command) = command else {
+            let database_connection = open_journal().await?;
+            return match cli.name {
+                Some(name) => substance::summarize_substance(&name, &database_connection).await,
+                None => ingestion::list_ingestions(Default::default(), &database_connection).await,
+            };
+        };
+
+        match command {
+            // Profiles are managed without opening any journal.
+            JournalCommand::Profile(profile_command) => profile::execute_profile_command(profile_command.command).await,
+            // Restoring replaces database file, so it must not be opened beforehand.
+            JournalCommand::Restore(restore) => {
+                profile::ensure_active_profile_exists()?;
+                let previous_journal = backup::restore_backup(restore).await?;
+
+                if let Some(previous_journal) = previous_journal {
+                    output::print_message(format!("Previous journal was saved to {}", previous_journal.display()));
+                }
+                output::print_message("Journal restored.");
+                Ok(())
+            }
+            JournalCommand::Database(database_command) => {
+                database::execute_database_command(database_command.command).await
+            }
+            JournalCommand::Substance(substance_command) => {
+                substance::execute_substance_command(substance_command.command, &open_journal().await?).await
+            }
+            JournalCommand::Ingestion(ingestion_command) => {
+                ingestion::execute_ingestion_command(ingestion_command, &open_journal().await?).await
+            }
+            JournalCommand::Export(export_command) => {
+                export::execute_export_command(export_command.command, &open_journal().await?).await
+            }
+            JournalCommand::Import(import_command) => {
+                import::execute_import_command(import_command.command, &open_journal().await?).await
+            }
+            JournalCommand::Stats(stats_command) => {
+                stats::execute_stats_command(stats_command, &open_journal().await?).await
+            }
+            JournalCommand::Chart(chart_command) => {
+                chart::execute_chart_command(chart_command, &open_journal().await?).await
+            }
+            JournalCommand::Limits(limits_command) => {
+                limits::execute_limits_command(limits_command.command, &open_journal().await?).await
+            }
+            JournalCommand::Again(again) => {
+                let (ingestion, substance, warnings) = ingestion::repeat_ingestion(again, &open_journal().await?).await?;
+                output::print_record(&ingestion::Ingestion::new(ingestion, substance))?;
+                limits::print_warnings(&warnings);
+                Ok(())
+            }
+            JournalCommand::Tui => tui::execute_tui_command(&open_journal().await?).await,
+            JournalCommand::Redose(redose_command) => {
+                redose::execute_redose_command(redose_command, &open_journal().await?).await
+            }
+            JournalCommand::Streaks(streaks_command) => {
+                streaks::execute_streaks_command(streaks_command, &open_journal().await?).await
+            }
+            JournalCommand::Backup(backup_command) => {
+                let backup_path = backup::create_backup(backup_command, &open_journal().await?).await?;
+                output::print_message(format!("Journal backed up to {}", backup_path.display()));
+                Ok(())
+            }
+        }
+    }
+
+    /// Connects to journal of active profile and applies pending migrations to it.
+    pub(super) async fn open_journal() -> Result<DatabaseConnection> {
+        profile::ensure_active_profile_exists()?;
+
+        let database_connection = db::connect_journal().await?;
+        db::migrate_database(&database_connection).await?;
+
+        if let Some(active_profile) = db::get_active_profile().filter(|profile| *profile != db::DEFAULT_PROFILE) {
+            output::print_notice(format!("Profile: {}", active_profile));
+        }
+
+        Ok(database_connection)
+    }
+}
+
+fn main() -> ExitCode {
+    // set_hook();
+    set_panic_hook();
+
+    CompleteEnv::with_factory(cli::Program::command)
+        .var(cli::completions::COMPLETE_VARIABLE)
+        .complete();
+
+    match task::block_on(cli::run_program()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            let exit_code = error.exit_code();
+            eprintln!("{:?}", miette::Report::new(error));
+            ExitCode::from(exit_code)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::database::{import_journal, merge_journal};
+    use crate::cli::export::{
+        ingestion_records, journal_document, substance_records, write_csv, write_journal_ndjson, ExportCsv,
+    };
+    use crate::cli::import::{
+        convert_csv, convert_psychonautwiki_export, parse_journal, ImportCsv,
+        PsychonautWikiExport,
+    };
+    use clap::Parser;
+    use crate::cli::ingestion::{create_ingestion, CreateIngestion, IngestionFilter};
+    use crate::cli::substance::{
+        create_substance, list_substances, update_substance, CreateSubstance, ListSubstance,
+    };
+    use chrono::{DateTime, Local};
+    use sea_migration::Migrator;
+    use sea_orm::{
+        ConnectionTrait, Database, DatabaseBackend, DatabaseConnection, DbBackend, EntityTrait,
+        MockDatabase, MockExecResult, Schema, Statement, Value,
+    };
+    use sea_orm_migration::{IntoSchemaManagerConnection, MigratorTrait};
+    use std::collections::BTreeMap;
+    use ulid::Ulid;
+
+    use super::*;
+
+    /// Utility to use a database that behaves like a real one
+    /// instead mock-up in which we know inputs and outputs.
+    async fn use_memory_sqlite() -> DatabaseConnection {
+        Database::connect("sqlite::memory:").await.unwrap()
+    }
+
+    async fn setup_schema(db: &DatabaseConnection) {
+        const DB_BACKEND: DbBackend = DbBackend::Sqlite;
+
+        let backend = db.get_database_backend();
+
+        async fn execute_create_table(
+            db: &DatabaseConnection,
+            backend: &DbBackend,
+            entity: impl EntityTrait,
+        ) {
+            db.execute(backend.build(&Schema::new(DB_BACKEND).create_table_from_entity(entity)))
+                .await
+                .expect("");
+        }
+
+        execute_create_table(db, &backend, sea_entity::substance::Entity).await;
+        execute_create_table(db, &backend, sea_entity::ingestion::Entity).await;
+        execute_create_table(db, &backend, sea_entity::dosage_limit::Entity).await;
+    }
+
+    #[async_std::test]
+    async fn test_create_substance() {
+        let db = use_memory_sqlite().await;
+        setup_schema(&db).await;
+
+        let command = CreateSubstance {
+            name: "caffeine".to_string(),
+            duration: None,
+            redose_interval: None,
+        };
+
+        let result = create_substance(command, &db).await;
+        assert!(result.is_ok());
+
+        let substance = result.unwrap();
+        assert_eq!(substance.alias, 1);
+        assert_eq!(substance.name, "caffeine");
+        assert!(Ulid::from_string(&substance.id).is_ok());
+    }
+
+    #[async_std::test]
+    async fn test_create_substance_with_mock() {
+        let caffeine_fixture = sea_entity::substance::Model {
+            id: "01J6NQ5SPZ2K9R3WDGCEHT4B8A".to_owned(),
+            alias: 78,
+            name: "caffeine".to_owned(),
+            duration_minutes: None,
+            redose_interval_minutes: None,
+        };
+
+        // Create a mock in-memory SQLite database
+        let db = MockDatabase::new(DatabaseBackend::Sqlite)
+            .append_query_results([[BTreeMap::from([("alias", Value::from(77))])]])
+            .append_query_results([[caffeine_fixture.clone()]])
+            .append_exec_results([MockExecResult {
+                last_insert_id: 78,
+                rows_affected: 1,
+            }])
+            .into_connection();
+
+        // Create the command to create a substance
+        let command = CreateSubstance {
+            name: "Caffeine".to_string(),
+            duration: None,
+            redose_interval: None,
+        };
+
+        // Call the create_substance function with the command and the reference to the database
+        let result = create_substance(command, &db).await;
+        assert!(result.is_ok());
+
+        let substance = result.unwrap();
+        assert_eq!(substance, caffeine_fixture);
+    }
+
+    #[async_std::test]
+    async fn test_update_substance_should_fail() {
+        let db = use_memory_sqlite().await;
+        setup_schema(&db).await;
+
+        let command = cli::substance::UpdateSubstance {
+            id: "1".to_string(),
+            name: Option::from("Coffee".to_string()),
+            duration: None,
+            redose_interval: None,
+        };
+
+        let result = update_substance(command, &db).await;
+        let error = result.unwrap_err();
+        assert!(matches!(error, crate::error::Error::UnknownSubstance(_)));
+        assert_eq!(error.exit_code(), 65);
+    }
+
+    #[async_std::test]
+    async fn test_list_substances() {
+        let caffeine_fixture = sea_entity::substance::Model {
+            id: "01J6NQ5SPZ2K9R3WDGCEHT4B8A".to_owned(),
+            alias: 78,
+            name: "caffeine".to_owned(),
+            duration_minutes: None,
+            redose_interval_minutes: None,
+        };
+
+        // Create a mock in-memory SQLite database
+        let db = MockDatabase::new(DatabaseBackend::Sqlite)
+            .append_query_results([[caffeine_fixture.clone()]])
+            .append_exec_results([MockExecResult {
+                last_insert_id: 78,
+                rows_affected: 1,
+            }])
+            .into_connection();
+
+        list_substances(ListSubstance { limit: 10, page: 0 }, &db)
+            .await
+            .unwrap();
+    }
+
+    #[async_std::test]
+    async fn test_update_substance() {
+        let db = use_memory_sqlite().await;
+        setup_schema(&db).await;
+
+        let caffeine = create_substance(
+            CreateSubstance {
+                name: "caffeine".to_owned(),
+                duration: None,
+                redose_interval: None,
+            },
+            &db,
+        )
+            .await
+            .expect("Substance should be created");
+
+        let command = cli::substance::UpdateSubstance {
+            id: "1".to_string(),
+            name: Option::from("Coffee".to_string()),
+            duration: None,
+            redose_interval: None,
+        };
+
+        let result = update_substance(command, &db).await;
+        assert!(result.is_ok());
+
+        let substance = result.unwrap();
+        assert_eq!(
+            substance,
+            sea_entity::substance::Model {
+                id: caffeine.id,
+                alias: 1,
+                name: "Coffee".to_owned(),
+                duration_minutes: None,
+                redose_interval_minutes: None,
+            }
+        );
+    }
+
+    #[async_std::test]
+    async fn test_create_ingestion() {
+        let db = use_memory_sqlite().await;
+        setup_schema(&db).await;
+
+        let caffeine = create_substance(
+            CreateSubstance {
+                name: "caffeine".to_owned(),
+                duration: None,
+                redose_interval: None,
+            },
+            &db,
+        )
+            .await
+            .expect("Substance should be created");
+
+        let command = CreateIngestion {
+            substance_id: "1".to_string(),
+            dosage_unit: Some("mg".to_string()),
+            dosage_amount: Some(20.0),
+            ingestion_date: DateTime::<Local>::default(),
+            route_of_administration: None,
+            notes: None,
+            force: false,
+        };
+
+        let result = create_ingestion(command, &db).await;
+        assert!(result.is_ok());
+
+        let (model, _) = result.unwrap();
+        assert_eq!(model.alias, 1);
+        assert_eq!(model.substance_id, caffeine.id);
+        assert_eq!(model.dosage_unit, "mg");
+        assert_eq!(model.dosage_value, 20.0);
+    }
+
+    #[async_std::test]
+    async fn test_create_ingestion_rejects_invalid_dose() {
+        let db = use_memory_sqlite().await;
+        setup_schema(&db).await;
+
+        create_substance(
+            CreateSubstance {
+                name: "caffeine".to_owned(),
+                duration: None,
+                redose_interval: None,
+            },
+            &db,
+        )
+            .await
+            .expect("Substance should be created");
+
+        for dosage_amount in [0.0, -5.0, f64::NAN] {
+            let command = CreateIngestion {
+                substance_id: "1".to_string(),
+                dosage_unit: Some("mg".to_string()),
+                dosage_amount: Some(dosage_amount),
+                ingestion_date: DateTime::<Local>::default(),
+                route_of_administration: None,
+                notes: None,
+                force: false,
+            };
+
+            let result = create_ingestion(command, &db).await;
+            assert!(matches!(result, Err(crate::error::Error::InvalidDose(_))));
+        }
+    }
+
+    #[async_std::test]
+    async fn test_migration_converts_identifiers_in_place() {
+        let db = use_memory_sqlite().await;
+        // Apply migrations which were creating integer primary keys.
+        Migrator::up(db.into_schema_manager_connection(), Some(2))
+            .await
+            .expect("Legacy schema should be created");
+
+        db.execute_unprepared(
+            "INSERT INTO substance (id, name) VALUES (4, 'caffeine'), (7, 'theanine');
+             INSERT INTO ingestion (id, substance_id, dosage_unit, dosage_value, ingested_at, created_at, updated_at)
+             VALUES (2, 7, 'mg', 200.0, '2024-08-20 08:00:00+00:00', '2024-08-20 08:00:00+00:00', '2024-08-20 08:00:00+00:00');",
+        )
+            .await
+            .expect("Legacy rows should be inserted");
+
+        Migrator::up(db.into_schema_manager_connection(), None)
+            .await
+            .expect("Identifiers should be migrated");
+
+        let substances = sea_entity::substance::Entity::find().all(&db).await.unwrap();
+        assert_eq!(substances.len(), 2);
+
+        let theanine = substances.iter().find(|s| s.name == "theanine").unwrap();
+        assert_eq!(theanine.alias, 7);
+        assert!(Ulid::from_string(&theanine.id).is_ok());
+
+        let ingestions = sea_entity::ingestion::Entity::find().all(&db).await.unwrap();
+        assert_eq!(ingestions.len(), 1);
+        assert_eq!(ingestions[0].alias, 2);
+        assert_eq!(ingestions[0].substance_id, theanine.id);
+        assert_eq!(ingestions[0].dosage_value, 200.0);
+    }
+
+    #[async_std::test]
+    async fn test_merge_journal() {
+        let target = use_memory_sqlite().await;
+        setup_schema(&target).await;
+        let source = use_memory_sqlite().await;
+        setup_schema(&source).await;
+
+        create_substance(
+            CreateSubstance {
+                name: "caffeine".to_owned(),
+                duration: None,
+                redose_interval: None,
+            },
+            &target,
+        )
+            .await
+            .unwrap();
+        create_ingestion(
+            CreateIngestion {
+                substance_id: "1".to_string(),
+                dosage_unit: Some("mg".to_string()),
+                dosage_amount: Some(100.0),
+                ingestion_date: DateTime::<Local>::default(),
+                route_of_administration: None,
+                notes: None,
+                force: false,
+            },
+            &target,
+        )
+            .await
+            .unwrap();
+
+        create_substance(
+            CreateSubstance {
+                name: " Caffeine ".to_owned(),
+                duration: None,
+                redose_interval: None,
+            },
+            &source,
+        )
+            .await
+            .unwrap();
+        create_substance(
+            CreateSubstance {
+                name: "theanine".to_owned(),
+                duration: None,
+                redose_interval: None,
+            },
+            &source,
+        )
+            .await
+            .unwrap();
+
+        for (substance_id, dosage_amount, ingestion_date) in [
+            // Exact copy of ingestion which exists in target journal.
+            ("1", 100.0, DateTime::<Local>::default()),
+            // Same moment as existing ingestion but different dose.
+            ("1", 200.0, DateTime::<Local>::default()),
+            ("2", 200.0, Local::now()),
+        ] {
+            create_ingestion(
+                CreateIngestion {
+                    substance_id: substance_id.to_string(),
+                    dosage_unit: Some("mg".to_string()),
+                    dosage_amount: Some(dosage_amount),
+                    ingestion_date,
+                    route_of_administration: None,
+                    notes: None,
+                    force: false,
+                },
+                &source,
+            )
+                .await
+                .unwrap();
+        }
+
+        let summary = merge_journal(&source, &target).await.unwrap();
+
+        assert_eq!(summary.substances_matched, 1);
+        assert_eq!(summary.substances_inserted, vec!["theanine".to_owned()]);
+        assert_eq!(summary.ingestions_inserted, 1);
+        assert_eq!(summary.ingestions_skipped, 1);
+        assert_eq!(summary.conflicts.len(), 1);
+
+        let substances = sea_entity::substance::Entity::find().all(&target).await.unwrap();
+        assert_eq!(substances.len(), 2);
+        let ingestions = sea_entity::ingestion::Entity::find().all(&target).await.unwrap();
+        assert_eq!(ingestions.len(), 2);
+
+        // Merging the same journal again should not change anything.
+        let summary = merge_journal(&source, &target).await.unwrap();
+        assert!(summary.substances_inserted.is_empty());
+        assert_eq!(summary.ingestions_inserted, 0);
+        assert_eq!(summary.ingestions_skipped, 2);
+    }
+
+    #[async_std::test]
+    async fn test_export_ingestions_csv() {
+        let db = use_memory_sqlite().await;
+        setup_schema(&db).await;
+
+        create_substance(
+            CreateSubstance {
+                name: "caffeine".to_owned(),
+                duration: None,
+                redose_interval: None,
+            },
+            &db,
+        )
+            .await
+            .unwrap();
+        create_substance(
+            CreateSubstance {
+                name: "theanine".to_owned(),
+                duration: None,
+                redose_interval: None,
+            },
+            &db,
+        )
+            .await
+            .unwrap();
+
+        for (substance_id, ingestion_date) in [
+            ("1", "2024-08-01T08:00:00+02:00"),
+            ("1", "2024-08-03T08:00:00+02:00"),
+            ("2", "2024-08-03T08:00:00+02:00"),
+        ] {
+            create_ingestion(
+                CreateIngestion {
+                    substance_id: substance_id.to_string(),
+                    dosage_unit: Some("mg".to_string()),
+                    dosage_amount: Some(100.0),
+                    ingestion_date: DateTime::parse_from_rfc3339(ingestion_date)
+                        .unwrap()
+                        .with_timezone(&Local),
+                    route_of_administration: None,
+                    notes: None,
+                    force: false,
+                },
+                &db,
+            )
+                .await
+                .unwrap();
+        }
+
+        let filter = IngestionFilter {
+            substance: Some("Caffeine".to_owned()),
+            since: Some(
+                DateTime::parse_from_rfc3339("2024-08-02T00:00:00+00:00")
+                    .unwrap()
+                    .with_timezone(&Local),
+            ),
+            until: None,
+        };
+
+        let records = ingestion_records(&filter, &db).await.unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].substance, "caffeine");
+
+        let mut output = Vec::new();
+        write_csv(&records, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let mut lines = output.lines();
+
+        assert_eq!(
+            lines.next(),
+            Some("id,alias,substance_id,substance,dosage_amount,dosage_unit,route_of_administration,notes,ingested_at,created_at,updated_at")
+        );
+        assert!(lines.next().unwrap().contains(",caffeine,100.0,mg,,,"));
+
+        let substances = substance_records(&filter, &db).await.unwrap();
+        let mut output = Vec::new();
+        write_csv(&substances, &mut output).unwrap();
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .starts_with("id,alias,name,duration_minutes,redose_interval_minutes\n"));
+
+        let export = ExportCsv::try_parse_from(["csv", "--substances", "--since", "yesterday"]);
+        assert!(export.is_err(), "Substances cannot be filtered by time");
+    }
+
+    #[async_std::test]
+    async fn test_json_journal_round_trip() {
+        let db = use_memory_sqlite().await;
+        setup_schema(&db).await;
+
+        create_substance(
+            CreateSubstance {
+                name: "caffeine".to_owned(),
+                duration: None,
+                redose_interval: None,
+            },
+            &db,
+        )
+            .await
+            .unwrap();
+        create_ingestion(
+            CreateIngestion {
+                substance_id: "caffeine".to_string(),
+                dosage_unit: Some("mg".to_string()),
+                dosage_amount: Some(0.1 + 0.2),
+                ingestion_date: Local::now(),
+                route_of_administration: None,
+                notes: None,
+                force: false,
+            },
+            &db,
+        )
+            .await
+            .unwrap();
+
+        let journal = journal_document(&db).await.unwrap();
+
+        let document = serde_json::to_string(&journal).unwrap();
+        let mut ndjson = Vec::new();
+        write_journal_ndjson(journal_document(&db).await.unwrap(), &mut ndjson).unwrap();
+
+        for exported in [document, String::from_utf8(ndjson).unwrap()] {
+            let restored_db = use_memory_sqlite().await;
+            setup_schema(&restored_db).await;
+
+            let parsed = parse_journal(&exported).unwrap();
+            let summary = import_journal(parsed.substances, parsed.ingestions, &restored_db, false)
+                .await
+                .unwrap();
+            assert_eq!(summary.ingestions_inserted, 1);
+
+            let restored = journal_document(&restored_db).await.unwrap();
+            assert_eq!(restored.substances, journal.substances);
+            assert_eq!(restored.ingestions, journal.ingestions);
+        }
+    }
+
+    #[async_std::test]
+    async fn test_import_psychonautwiki_export() {
+        let export = r#"{
+            "experiences": [
+                {
+                    "title": "Morning focus",
+                    "creationDate": 1723100000000,
+                    "ingestions": [
+                        {
+                            "substanceName": "Caffeine",
+                            "time": 1723100000000,
+                            "creationDate": 1723100005000,
+                            "administrationRoute": "ORAL",
+                            "dose": 100.0,
+                            "units": "mg",
+                            "notes": "with breakfast"
+                        },
+                        {
+                            "substanceName": "L-Theanine",
+                            "time": 1723100000000,
+                            "endTime": null,
+                            "creationDate": 1723100005000,
+                            "administrationRoute": "ORAL",
+                            "dose": 2.0,
+                            "isDoseAnEstimate": false,
+                            "estimatedDoseStandardDeviation": null,
+                            "units": "mg",
+                            "isKnown": true,
+                            "notes": "",
+                            "stomachFullness": null,
+                            "consumerName": null,
+                            "customUnitId": 1
+                        },
+                        {
+                            "substanceName": "Caffeine",
+                            "time": 1723110000000,
+                            "administrationRoute": "ORAL",
+                            "dose": null,
+                            "units": "mg"
+                        }
+                    ]
+                }
+            ],
+            "customSubstances": [{ "id": 1, "name": "Homemade tea", "units": "cups", "description": "" }],
+            "customUnits": [
+                {
+                    "id": 1,
+                    "substanceName": "L-Theanine",
+                    "name": "Capsules from pharmacy",
+                    "creationDate": 1723000000000,
+                    "administrationRoute": "ORAL",
+                    "dose": 100.0,
+                    "estimatedDoseStandardDeviation": null,
+                    "isEstimate": false,
+                    "isArchived": false,
+                    "unit": "capsule",
+                    "unitPlural": "capsules",
+                    "originalUnit": "mg",
+                    "note": ""
+                }
+            ]
+        }"#;
+
+        let db = use_memory_sqlite().await;
+        setup_schema(&db).await;
+        create_substance(
+            CreateSubstance {
+                name: "theanine".to_owned(),
+                duration: None,
+                redose_interval: None,
+            },
+            &db,
+        )
+            .await
+            .unwrap();
+
+        let export: PsychonautWikiExport = serde_json::from_str(export).unwrap();
+        let journal = convert_psychonautwiki_export(
+            export,
+            &[("l-theanine".to_owned(), "Theanine".to_owned())],
+        );
+
+        assert_eq!(journal.ingestions.len(), 2);
+        assert_eq!(journal.rejected.len(), 1);
+        assert_eq!(journal.rejected[0].reason, "Unknown dose");
+
+        let caffeine = &journal.ingestions[0];
+        assert_eq!(caffeine.route_of_administration.as_deref(), Some("oral"));
+        assert_eq!(caffeine.notes.as_deref(), Some("Morning focus\nwith breakfast"));
+        assert_eq!(caffeine.ingested_at.timestamp_millis(), 1723100000000);
+        assert_eq!(caffeine.created_at.timestamp_millis(), 1723100005000);
+
+        let theanine = &journal.ingestions[1];
+        assert_eq!(theanine.dosage_value, 200.0);
+        assert_eq!(theanine.dosage_unit, "mg");
+
+        let summary = import_journal(
+            journal.substances.clone(),
+            journal.ingestions.clone(),
+            &db,
+            true,
+        )
+            .await
+            .unwrap();
+        assert_eq!(summary.substances_inserted, vec!["Homemade tea".to_owned(), "Caffeine".to_owned()]);
+        assert_eq!(summary.ingestions_inserted, 2);
+        assert_eq!(
+            sea_entity::ingestion::Entity::find().all(&db).await.unwrap().len(),
+            0,
+            "Dry run should not save anything"
+        );
+
+        import_journal(journal.substances, journal.ingestions, &db, false)
+            .await
+            .unwrap();
+        assert_eq!(sea_entity::substance::Entity::find().all(&db).await.unwrap().len(), 3);
+        assert_eq!(sea_entity::ingestion::Entity::find().all(&db).await.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_convert_csv_with_column_mapping() {
+        let content = "Drug;Dose;Date;Zone;Comment\n\
+                       Caffeine;100 mg;2024-08-01 08:00;Europe/Warsaw;espresso\n\
+                       caffeine;0,2g;2024-08-01T14:00:00+00:00;;\n\
+                       Theanine;a lot;2024-08-01 08:00;;\n\
+                       Theanine;200;not a date;;\n\
+                       Theanine;200;2024-08-01 08:00;+05:00;\n\
+                       Theanine;-200 mg;2024-08-01 09:00;;\n\
+                       Theanine;0;2024-08-01 09:00;;\n\
+                       Caffeine;1,000 mg;2024-08-01 09:00;;\n";
+
+        let import_csv = ImportCsv::try_parse_from([
+            "csv",
+            "-",
+            "--delimiter",
+            ";",
+            "--substance-column",
+            "Drug",
+            "--amount-column",
+            "Dose",
+            "--time-column",
+            "Date",
+            "--timezone-column",
+            "Zone",
+            "--notes-column",
+            "Comment",
+        ])
+            .unwrap();
+
+        let journal = convert_csv(content, &import_csv).unwrap();
+        assert!(ImportCsv::try_parse_from(["csv", "-", "--delimiter", "§"]).is_err());
+
+        assert_eq!(journal.substances.len(), 2);
+        assert_eq!(journal.ingestions.len(), 3);
+        assert_eq!(journal.rejected.len(), 5);
+        assert_eq!(journal.rejected[0].entry, "line 4");
+        assert_eq!(journal.rejected[2].reason, "Amount \"-200 mg\" is not positive");
+        assert_eq!(journal.rejected[3].reason, "Amount \"0\" is not positive");
+        assert!(journal.rejected[4].reason.starts_with("Ambiguous amount \"1,000 mg\""));
+
+        let espresso = &journal.ingestions[0];
+        assert_eq!(espresso.dosage_value, 100.0);
+        assert_eq!(espresso.dosage_unit, "mg");
+        assert_eq!(espresso.notes.as_deref(), Some("espresso"));
+        assert_eq!(espresso.ingested_at.to_rfc3339(), "2024-08-01T08:00:00+02:00");
+
+        let second = &journal.ingestions[1];
+        assert_eq!(second.substance_id, espresso.substance_id);
+        assert_eq!(second.dosage_value, 0.2);
+        assert_eq!(second.dosage_unit, "g");
+        assert_eq!(second.ingested_at.to_rfc3339(), "2024-08-01T14:00:00+00:00");
+
+        assert_eq!(
+            journal.ingestions[2].ingested_at.to_rfc3339(),
+            "2024-08-01T08:00:00+05:00"
+        );
+    }
+
+    #[async_std::test]
+    async fn test_backup_database_and_validate_it() {
+        let directory = std::env::temp_dir().join(format!("neuronek-test-{}", Ulid::new()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        // `VACUUM INTO` does not write anything for in-memory databases.
+        let db = Database::connect(format!(
+            "{}?mode=rwc",
+            db::get_database_uri_for(&directory.join("journal.db"))
+        ))
+            .await
+            .unwrap();
+        Migrator::up(db.into_schema_manager_connection(), None)
+            .await
+            .unwrap();
+        create_substance(
+            CreateSubstance {
+                name: "caffeine".to_owned(),
+                duration: None,
+                redose_interval: None,
+            },
+            &db,
+        )
+            .await
+            .unwrap();
+
+        let backup_path = directory.join("backups").join("backup.db");
+
+        db::backup_database(&db, &backup_path).await.unwrap();
+        assert!(db::backup_database(&db, &backup_path).await.is_err());
+
+        let backup = Database::connect(db::get_database_uri_for(&backup_path))
+            .await
+            .unwrap();
+        assert_eq!(db::validate_journal_database(&backup).await.unwrap(), 0);
+        assert_eq!(
+            sea_entity::substance::Entity::find().all(&backup).await.unwrap().len(),
+            1
+        );
+
+        backup
+            .execute_unprepared(
+                "INSERT INTO seaql_migrations (version, applied_at) VALUES ('m99990101_000001_from_future', 0)",
+            )
+            .await
+            .unwrap();
+        assert!(db::validate_journal_database(&backup).await.is_err());
+
+        backup.close().await.unwrap();
+        db.close().await.unwrap();
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_rotate_backups() {
+        let directory = std::env::temp_dir().join(format!("neuronek-test-{}", Ulid::new()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        for day in 1..=7 {
+            std::fs::write(directory.join(format!("pre-migration-2024080{}-120000000.db", day)), "").unwrap();
+        }
+        std::fs::write(directory.join("backup-20240801-120000000.db"), "").unwrap();
+
+        db::rotate_backups(&directory, "pre-migration", 5).unwrap();
+
+        let mut remaining: Vec<String> = std::fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        remaining.sort();
+
+        assert_eq!(remaining.len(), 6);
+        assert_eq!(remaining[0], "backup-20240801-120000000.db");
+        assert_eq!(remaining[1], "pre-migration-20240803-120000000.db");
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_encrypt_and_decrypt_with_passphrase() {
+        use age::secrecy::SecretString;
+
+        let passphrase = SecretString::from("correct horse battery staple".to_string());
+        let plaintext = b"substance,amount\ncaffeine,100\n";
+
+        for armor in [false, true] {
+            let ciphertext = crypto::encrypt(plaintext, &passphrase, armor).unwrap();
+            assert!(crypto::is_encrypted(&ciphertext));
+            assert_eq!(crypto::decrypt(&ciphertext, &passphrase).unwrap(), plaintext);
+        }
+
+        let ciphertext = crypto::encrypt(plaintext, &passphrase, false).unwrap();
+        let wrong_passphrase = SecretString::from("incorrect".to_string());
+        assert!(crypto::decrypt(&ciphertext, &wrong_passphrase).is_err());
+        assert!(!crypto::is_encrypted(plaintext));
+    }
+
+    #[async_std::test]
+    async fn test_encrypt_and_decrypt_database_in_place() {
+        use age::secrecy::SecretString;
+
+        let directory = std::env::temp_dir().join(format!("neuronek-test-{}", Ulid::new()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("journal.db");
+        let passphrase = SecretString::from("correct horse battery staple".to_string());
+
+        let db = db::connect_database(&path, false, None).await.unwrap();
+        db.execute_unprepared("CREATE TABLE substance (name TEXT NOT NULL); INSERT INTO substance VALUES ('caffeine');")
+            .await
+            .unwrap();
+        db.close().await.unwrap();
+        assert!(!db::is_database_encrypted(&path));
+
+        db::encrypt_database(&path, &passphrase).await.unwrap();
+        assert!(db::is_database_encrypted(&path));
+
+        let wrong_passphrase = SecretString::from("incorrect".to_string());
+        assert!(db::connect_database(&path, true, Some(&wrong_passphrase)).await.is_err());
+
+        let db = db::connect_database(&path, true, Some(&passphrase)).await.unwrap();
+        let row = db
+            .query_one(Statement::from_string(DbBackend::Sqlite, "SELECT name FROM substance"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(row.try_get::<String>("", "name").unwrap(), "caffeine");
+        db.close().await.unwrap();
+
+        db::decrypt_database(&path, &passphrase).await.unwrap();
+        assert!(!db::is_database_encrypted(&path));
+
+        let db = db::connect_database(&path, true, None).await.unwrap();
+        assert!(db
+            .query_one(Statement::from_string(DbBackend::Sqlite, "SELECT name FROM substance"))
+            .await
+            .unwrap()
+            .is_some());
+        db.close().await.unwrap();
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_configuration_file_overrides_defaults() {
+        let directory = std::env::temp_dir().join(format!("neuronek-test-{}", Ulid::new()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let config_file = directory.join("config.toml");
+        std::fs::write(&config_file, "dosage_unit = \"g\"\n[warnings]\nlimit_ratio = 0.5\n").unwrap();
+
+        let figment = config::figment(Some(&config_file), config::Arguments::default()).unwrap();
+        let configuration: config::Configuration = figment.extract().unwrap();
+        assert_eq!(configuration.dosage_unit, "g");
+        assert_eq!(configuration.warnings.limit_ratio, 0.5);
+        assert_eq!(configuration.output_format, crate::output::OutputFormat::Table);
+
+        let values = config::describe(&figment).unwrap();
+        let dosage_unit = values.iter().find(|value| value.key == "dosage_unit").unwrap();
+        assert_eq!(dosage_unit.value, "g");
+        assert_eq!(dosage_unit.source, format!("file {}", config_file.display()));
+        let route = values
+            .iter()
+            .find(|value| value.key == "route_of_administration")
+            .unwrap();
+        assert_eq!((route.value.as_str(), route.source.as_str()), ("-", "default"));
+
+        assert!(config::figment(Some(&directory.join("missing.toml")), config::Arguments::default()).is_err());
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
 
-        #[derive(Parser)]
-        #[command(args_conflicts_with_subcommands = true)]
-        pub(crate) struct IngestionCommand {
-            #[command(subcommand)]
-            pub command: IngestionCommands,
-        }
+    #[test]
+    fn test_database_location_from_argument() {
+        let database = std::path::Path::new("sqlite:///var/lib/neuronek/journal.db?mode=rwc");
+        let arguments = config::Arguments { database_path: Some(database), ..Default::default() };
+        let figment = config::figment(None, arguments).unwrap();
 
-        pub async fn create_ingestion(
-            create_ingestion_command: CreateIngestion,
-            db_conn: &DatabaseConnection,
-        ) -> Result<sea_entity::ingestion::Model, DbErr> {
-            let active_model = sea_entity::ingestion::ActiveModel {
-                id: Default::default(),
-                substance_id: ActiveValue::Set(create_ingestion_command.substance_id),
-                dosage_unit: ActiveValue::Set(create_ingestion_command.dosage_unit),
-                dosage_value: ActiveValue::Set(create_ingestion_command.dosage_amount),
-                ingested_at: ActiveValue::Set(create_ingestion_command.ingestion_date.into()),
-                created_at: ActiveValue::Set(Utc::now().into()),
-                updated_at: ActiveValue::Set(Utc::now().into()),
-            };
+        let values = config::describe(&figment).unwrap();
+        let database_path = values.iter().find(|value| value.key == "database_path").unwrap();
+        assert_eq!(database_path.value, "/var/lib/neuronek/journal.db");
+        assert_eq!(database_path.source, "command line argument");
 
-            let model = active_model.insert(db_conn).await.unwrap();
-            model.try_into_model()
-        }
+        let arguments = config::Arguments {
+            database_path: Some(std::path::Path::new("sqlite::memory:")),
+            ..Default::default()
+        };
+        let in_memory = config::figment(None, arguments).unwrap();
+        assert!(config::describe(&in_memory).is_err());
+    }
 
-        pub async fn execute_ingestion_command(
-            ingestion_command: IngestionCommand,
-            db_conn: &DatabaseConnection,
-        ) {
-            match ingestion_command.command {
-                IngestionCommands::Create(payload) => {
-                    create_ingestion(payload, db_conn)
-                        .await
-                        .expect("Should create ingestion");
-                }
-            }
-        }
+    #[test]
+    fn test_profile_names_and_journals() {
+        use crate::cli::profile::{delete_profile, rename_profile, validate_profile_name, DeleteProfile, RenameProfile};
+
+        assert!(validate_profile_name("household-member_2").is_ok());
+        assert!(validate_profile_name("").is_err());
+        assert!(validate_profile_name("../other").is_err());
+
+        let error = delete_profile(DeleteProfile { name: "..".to_owned(), yes: true }).unwrap_err();
+        assert!(matches!(error, crate::error::Error::InvalidInput(_)));
+        let error = rename_profile(RenameProfile {
+            name: "..".to_owned(),
+            new_name: "other".to_owned(),
+        })
+            .unwrap_err();
+        assert!(matches!(error, crate::error::Error::InvalidInput(_)));
+
+        assert_eq!(
+            db::get_profile_database_path(db::DEFAULT_PROFILE).unwrap(),
+            db::get_data_directory().unwrap().join("data.db")
+        );
+        assert_eq!(
+            db::get_profile_database_path("patient").unwrap(),
+            db::get_data_directory().unwrap().join("profiles").join("patient").join("data.db")
+        );
     }
 
-    #[derive(Subcommand)]
-    pub(super) enum ProgramCommand {
-        Substance(substance::SubstanceCommand),
-        Ingestion(ingestion::IngestionCommand),
+    #[test]
+    fn test_logging_filter_for_debug_flags() {
+        use tracing_subscriber::filter::EnvFilter;
+
+        for verbosity in 0..=4 {
+            let directives = crate::logging::filter_directives(verbosity);
+            assert!(EnvFilter::try_new(directives).is_ok());
+            assert_eq!(directives.contains("sqlx"), verbosity >= 3);
+        }
     }
 
-    #[derive(Parser)]
-    #[command(
-        version = "0.0.1-dev",
-        about = "Dosage journal that knows!",
-        long_about = "🧬 Intelligent dosage tracker application with purpose to monitor supplements, nootropics and psychoactive substances along with their long-term influence on one's mind and body."
-    )]
-    pub(super) struct Program {
-        /// Optional name to operate on
-        pub name: Option<String>,
+    #[test]
+    fn test_render_records_in_output_formats() {
+        use crate::cli::profile::Profile;
+        use crate::output::{render, render_record, OutputFormat};
 
-        /// Sets a custom config file
-        #[arg(short, long, value_name = "FILE")]
-        pub config: Option<PathBuf>,
+        let profiles = vec![
+            Profile {
+                name: String::from("default"),
+                active: String::from("*"),
+                size: String::from("4 KiB"),
+                path: String::from("/data/data.db"),
+            },
+            Profile {
+                name: String::from("patient"),
+                active: String::new(),
+                size: String::from("-"),
+                path: String::from("/data/profiles/patient/data.db"),
+            },
+        ];
 
-        /// Turn debugging information on
-        #[arg(short, long, action = clap::ArgAction::Count)]
-        pub debug: u8,
+        let json: serde_json::Value = serde_json::from_str(&render(&profiles, OutputFormat::Json).unwrap()).unwrap();
+        assert_eq!(json[1]["name"], "patient");
 
-        #[command(subcommand)]
-        pub command: ProgramCommand,
+        let ndjson = render(&profiles, OutputFormat::Ndjson).unwrap();
+        assert_eq!(ndjson.lines().count(), 2);
+        assert!(ndjson.lines().all(|line| serde_json::from_str::<serde_json::Value>(line).is_ok()));
+
+        let csv = render(&profiles, OutputFormat::Csv).unwrap();
+        assert_eq!(csv.lines().next(), Some("name,active,size,path"));
+
+        let plain = render(&profiles, OutputFormat::Plain).unwrap();
+        assert_eq!(plain.lines().next(), Some("default\t*\t4 KiB\t/data/data.db"));
+
+        let yaml = render(&profiles, OutputFormat::Yaml).unwrap();
+        assert!(yaml.starts_with("- name: default"));
+
+        let record: serde_json::Value =
+            serde_json::from_str(&render_record(&profiles[0], OutputFormat::Json).unwrap()).unwrap();
+        assert_eq!(record["path"], "/data/data.db");
+        assert_eq!(render(&Vec::<Profile>::new(), OutputFormat::Json).unwrap(), "[]");
     }
 
-    pub(super) async fn run_program() {
-        let cli = Program::parse();
+    #[test]
+    fn test_command_line_definition() {
+        use clap::CommandFactory;
 
-        match cli.command {
-            ProgramCommand::Substance(substance_command) => {
-                substance::execute_substance_command(
-                    substance_command.command,
-                    db::DATABASE_CONNECTION.deref(),
-                )
-                    .await;
-            }
-            ProgramCommand::Ingestion(ingestion_command) => {
-                ingestion::execute_ingestion_command(
-                    ingestion_command,
-                    db::DATABASE_CONNECTION.deref(),
-                )
-                    .await;
+        cli::Program::command().debug_assert();
+    }
+
+    #[test]
+    fn test_statistics_per_week() {
+        use crate::cli::ingestion::normalize_dosage;
+        use crate::cli::stats::{calculate_statistics, Dose, Period};
+        use chrono::NaiveDate;
+
+        let dose = |amount: f64, unit: &str, date: &str| {
+            let (amount, unit) = normalize_dosage(amount, unit);
+            Dose {
+                substance: String::from("caffeine"),
+                unit,
+                amount,
+                date: NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap(),
+                time: chrono::NaiveTime::default(),
             }
-        }
+        };
+
+        let doses = vec![
+            dose(100.0, "mg", "2024-08-05"),
+            dose(200.0, "mg", "2024-08-05"),
+            dose(0.1, " G", "2024-08-07"),
+            dose(100.0, "mg", "2024-08-13"),
+        ];
+        let until = NaiveDate::from_ymd_opt(2024, 8, 18).unwrap();
+        let statistics = calculate_statistics(&doses, Period::Week, None, until);
+
+        assert_eq!(statistics.len(), 2);
+        let (first, second) = (&statistics[0], &statistics[1]);
+        assert_eq!(first.period, "2024-W32");
+        assert_eq!(first.unit, "mg");
+        assert_eq!((first.count, first.total, first.mean), (3, 400.0, Some(133.33)));
+        assert_eq!((first.min, first.max), (Some(100.0), Some(200.0)));
+        assert_eq!((first.days_used, first.days_abstinent), (2, 5));
+        assert_eq!((first.average_7d, first.change), (57.14, None));
+
+        assert_eq!(second.period, "2024-W33");
+        assert_eq!((second.count, second.days_used, second.days_abstinent), (1, 1, 6));
+        assert_eq!((second.average_30d, second.change), (16.67, Some(-75.0)));
     }
-}
 
-fn main() {
-    // set_hook();
-    set_panic_hook();
+    #[test]
+    fn test_chart_rendering() {
+        use crate::cli::chart::{bar, heatmap, sparkline};
+        use chrono::NaiveDate;
+        use std::collections::BTreeMap;
 
-    task::block_on(async {
-        db::migrate_database(&db::DATABASE_CONNECTION).await;
-        cli::run_program().await;
-    });
-}
+        assert_eq!(sparkline(&[0.0, 10.0, 40.0, 80.0]), " ▁▄█");
+        assert_eq!(bar(50.0, 100.0, 4), "██");
+        assert_eq!(bar(10.0, 100.0, 4), "▍");
+        assert_eq!(bar(0.0, 100.0, 4), "");
 
-#[cfg(test)]
-mod tests {
-    use crate::cli::ingestion::{create_ingestion, CreateIngestion};
-    use crate::cli::substance::{
-        create_substance, list_substances, update_substance, CreateSubstance, ListSubstance,
-    };
-    use chrono::{DateTime, Local, Utc};
-    use sea_orm::{
-        ConnectionTrait, Database, DatabaseBackend, DatabaseConnection, DbBackend, EntityTrait,
-        MockDatabase, MockExecResult, Schema,
-    };
+        let end = NaiveDate::from_ymd_opt(2024, 8, 7).unwrap();
+        let counts = BTreeMap::from([(NaiveDate::from_ymd_opt(2024, 8, 5).unwrap(), 2), (end, 1)]);
+        let lines = heatmap(&counts, end, 4);
 
-    use super::*;
+        assert_eq!(lines.len(), 8);
+        assert_eq!(lines[0], "    Jul");
+        assert_eq!(lines[1], "Mon ···█");
+        assert_eq!(lines[3], "Wed ···▒");
+        assert_eq!(lines[7], "Sun ···");
 
-    /// Utility to use a database that behaves like a real one
-    /// instead mock-up in which we know inputs and outputs.
-    async fn use_memory_sqlite() -> DatabaseConnection {
-        Database::connect("sqlite::memory:").await.unwrap()
+        assert!(crate::cli::chart::Chart::try_parse_from(["chart", "--days", "0"]).is_err());
     }
 
-    async fn setup_schema(db: &DatabaseConnection) {
-        const DB_BACKEND: DbBackend = DbBackend::Sqlite;
+    #[test]
+    fn test_distribution_by_hour_weekday_and_month() {
+        use crate::cli::stats::{calculate_distribution, Dose};
+        use chrono::{NaiveDate, NaiveTime};
 
-        let backend = db.get_database_backend();
+        let dose = |date: &str, time: &str| Dose {
+            substance: String::from("caffeine"),
+            unit: String::from("mg"),
+            amount: 100.0,
+            date: NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap(),
+            time: NaiveTime::parse_from_str(time, "%H:%M").unwrap(),
+        };
 
-        async fn execute_create_table(
-            db: &DatabaseConnection,
-            backend: &DbBackend,
-            entity: impl EntityTrait,
-        ) {
-            db.execute(backend.build(&Schema::new(DB_BACKEND).create_table_from_entity(entity)))
-                .await
-                .expect("");
-        }
+        let doses = vec![
+            dose("2024-07-01", "08:00"),
+            dose("2024-07-02", "09:00"),
+            dose("2024-08-05", "10:30"),
+            dose("2024-08-06", "11:30"),
+        ];
+        let distribution = calculate_distribution(&doses);
+        let find = |kind: &str, bucket: &str| {
+            distribution
+                .iter()
+                .find(|row| row.kind == kind && row.bucket == bucket)
+                .unwrap()
+        };
 
-        execute_create_table(db, &backend, sea_entity::substance::Entity).await;
-        execute_create_table(db, &backend, sea_entity::ingestion::Entity).await;
+        assert_eq!(find("all", "all").median_time.as_deref(), Some("09:45"));
+        assert_eq!(distribution.iter().filter(|row| row.kind == "hour").count(), 4);
+        assert_eq!(find("hour", "10").count, 1);
+        assert_eq!(find("weekday", "Mon").count, 2);
+        assert_eq!(find("weekday", "Sun").count, 0);
+        assert_eq!(find("month", "2024-07").median_time.as_deref(), Some("08:30"));
+        assert_eq!(find("month", "2024-08").drift_minutes, Some(150));
     }
 
     #[async_std::test]
-    async fn test_create_substance() {
-        let caffeine_fixture = sea_entity::substance::Model {
-            id: 1,
-            name: "caffeine".to_owned(),
-        };
+    async fn test_limits_track_usage_of_period() {
+        use crate::cli::limits::{describe_warning, limit_usage, list_limits, set_limit, LimitStatus, SetLimit};
+        use crate::cli::stats::{Dose, Period};
+        use chrono::NaiveDate;
 
         let db = use_memory_sqlite().await;
         setup_schema(&db).await;
+        create_substance(
+            CreateSubstance {
+                name: "caffeine".to_owned(),
+                duration: None,
+                redose_interval: None,
+            },
+            &db,
+        )
+            .await
+            .unwrap();
 
-        let command = CreateSubstance {
-            name: "caffeine".to_string(),
+        let limit = set_limit(
+            SetLimit {
+                substance: "caffeine".to_owned(),
+                amount: Some(0.3),
+                unit: Some("g".to_owned()),
+                days: None,
+                per: Period::Day,
+            },
+            &db,
+        )
+            .await
+            .unwrap();
+        assert_eq!((limit.max_amount, limit.unit.as_deref()), (Some(300.0), Some("mg")));
+
+        let mut warnings = Vec::new();
+        for dosage_amount in [200.0, 150.0] {
+            let (_, ingestion_warnings) = create_ingestion(
+                CreateIngestion {
+                    substance_id: "caffeine".to_string(),
+                    dosage_unit: Some("mg".to_string()),
+                    dosage_amount: Some(dosage_amount),
+                    ingestion_date: Local::now(),
+                    route_of_administration: None,
+                    notes: None,
+                    force: false,
+                },
+                &db,
+            )
+                .await
+                .expect("Ingestion over limit should be only reported by default");
+            warnings.push(ingestion_warnings);
+        }
+        assert!(warnings[0].is_empty());
+        assert_eq!(warnings[1].len(), 1);
+        assert!(describe_warning(&warnings[1][0]).starts_with("Ingestion exceeds limit of caffeine 300 mg per day"));
+
+        let usages = list_limits(&db).await.unwrap();
+        assert_eq!(usages.len(), 1);
+        assert_eq!((usages[0].used, usages[0].status), (350.0, LimitStatus::Exceeded));
+
+        let days_limit = sea_entity::dosage_limit::Model {
+            period: String::from("week"),
+            max_amount: None,
+            unit: None,
+            max_days: Some(3),
+            ..limit
         };
+        let dose = |day: u32| Dose {
+            substance: String::from("caffeine"),
+            unit: String::from("mg"),
+            amount: 100.0,
+            date: NaiveDate::from_ymd_opt(2024, 8, day).unwrap(),
+            time: chrono::NaiveTime::default(),
+        };
+        let doses = vec![dose(5), dose(5), dose(7)];
+        let date = NaiveDate::from_ymd_opt(2024, 8, 7).unwrap();
+        let usage = limit_usage(&days_limit, "caffeine", date, &doses, 0.6).unwrap();
+        assert_eq!((usage.used, usage.unit.as_str(), usage.window.as_str()), (2.0, "days", "2024-W32"));
+        assert_eq!(usage.status, LimitStatus::Approaching);
+    }
 
-        let result = create_substance(command, &db).await;
-        assert!(result.is_ok());
+    #[test]
+    fn test_streaks_and_relapses() {
+        use crate::cli::streaks::{calculate_streaks, format_duration};
+        use chrono::NaiveDateTime;
 
-        let substance = result.unwrap();
-        assert_eq!(substance, caffeine_fixture);
+        let time = |time: &str| NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M").unwrap();
+        let uses = vec![
+            time("2024-08-10 09:00"),
+            time("2024-08-01 09:00"),
+            time("2024-08-02 18:00"),
+            time("2024-08-02 09:00"),
+            time("2024-08-04 09:00"),
+        ];
+
+        let (streaks, relapses) = calculate_streaks("caffeine", &uses, time("2024-08-13 11:30"), 2).unwrap();
+        assert_eq!(streaks.last_use, "2024-08-10 09:00");
+        assert_eq!(streaks.since_last_use, "3d 2h 30m");
+        assert_eq!((streaks.current_streak, streaks.longest_streak), (3, 5));
+        assert_eq!(relapses.len(), 1);
+        assert_eq!((relapses[0].date.to_string().as_str(), relapses[0].abstinent_days), ("2024-08-10", 5));
+
+        assert_eq!(format_duration(chrono::TimeDelta::minutes(75)), "1h 15m");
+        assert!(calculate_streaks("caffeine", &[], time("2024-08-13 11:30"), 2).is_none());
     }
 
-    #[async_std::test]
-    async fn test_create_substance_with_mock() {
-        let caffeine_fixture = sea_entity::substance::Model {
-            id: 78,
-            name: "caffeine".to_owned(),
+    #[test]
+    fn test_redose_guidance() {
+        use crate::cli::redose::{redose_guidance, RedoseStatus};
+        use crate::cli::stats::Dose;
+        use crate::cli::substance::parse_duration;
+        use chrono::NaiveDateTime;
+
+        let time = |time: &str| NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M").unwrap();
+        let dose = |at: &str, amount: f64| Dose {
+            substance: String::from("caffeine"),
+            unit: String::from("mg"),
+            amount,
+            date: time(at).date(),
+            time: time(at).time(),
         };
+        let doses = vec![dose("2024-08-01 06:00", 100.0), dose("2024-08-01 08:00", 50.0)];
 
-        // Create a mock in-memory SQLite database
-        let db = MockDatabase::new(DatabaseBackend::Sqlite)
-            .append_query_results([[caffeine_fixture.clone()]])
-            .append_exec_results([MockExecResult {
-                last_insert_id: 78,
-                rows_affected: 1,
-            }])
-            .into_connection();
+        assert_eq!(parse_duration("1h30m").unwrap(), chrono::TimeDelta::minutes(90));
+        assert!(parse_duration("90").is_err());
 
-        // Create the command to create a substance
-        let command = CreateSubstance {
-            name: "Caffeine".to_string(),
+        let mut caffeine = sea_entity::substance::Model {
+            id: "01J6NQ5SPZ2K9R3WDGCEHT4B8A".to_owned(),
+            alias: 1,
+            name: "caffeine".to_owned(),
+            duration_minutes: Some(5 * 60),
+            redose_interval_minutes: Some(3 * 60),
         };
 
-        // Call the create_substance function with the command and the reference to the database
-        let result = create_substance(command, &db).await;
-        assert!(result.is_ok());
+        let guidance = redose_guidance(&caffeine, &doses, time("2024-08-01 10:00")).unwrap();
+        assert_eq!(guidance.status, RedoseStatus::TooEarly);
+        assert_eq!((guidance.active_amount, guidance.wait.as_deref()), (150.0, Some("1h 0m")));
 
-        let substance = result.unwrap();
-        assert_eq!(substance, caffeine_fixture);
+        let guidance = redose_guidance(&caffeine, &doses, time("2024-08-01 12:00")).unwrap();
+        assert_eq!((guidance.status, guidance.active_amount), (RedoseStatus::Active, 50.0));
+
+        let guidance = redose_guidance(&caffeine, &doses, time("2024-08-01 13:00")).unwrap();
+        assert_eq!((guidance.status, guidance.active_amount), (RedoseStatus::Clear, 0.0));
+
+        caffeine.duration_minutes = None;
+        caffeine.redose_interval_minutes = None;
+        let guidance = redose_guidance(&caffeine, &doses, time("2024-08-01 13:00")).unwrap();
+        assert_eq!((guidance.status, guidance.active_amount), (RedoseStatus::Unknown, 150.0));
     }
 
-    #[async_std::test]
-    async fn test_update_substance_should_fail() {
-        let db = use_memory_sqlite().await;
-        setup_schema(&db).await;
+    #[test]
+    fn test_tui_navigation_and_quick_log() {
+        use crate::cli::tui::{Action, App, View};
+        use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 
-        let command = cli::substance::UpdateSubstance {
-            id: 1,
-            name: Option::from("Coffee".to_string()),
+        let substance = |alias: i32, name: &str| sea_entity::substance::Model {
+            id: format!("01J6NQ5SPZ2K9R3WDGCEHT4B8{}", alias),
+            alias,
+            name: name.to_owned(),
+            duration_minutes: None,
+            redose_interval_minutes: None,
         };
+        let mut app = App {
+            substances: vec![substance(1, "Caffeine"), substance(2, "Theanine")],
+            ..Default::default()
+        };
+        let press = |app: &mut App, code: KeyCode| app.handle_key(KeyEvent::new(code, KeyModifiers::NONE));
 
-        let result = update_substance(command, &db).await;
-        assert!(result.is_err());
+        assert_eq!(app.view(), View::Today);
+        press(&mut app, KeyCode::Char('3'));
+        press(&mut app, KeyCode::Char('/'));
+        press(&mut app, KeyCode::Char('t'));
+        press(&mut app, KeyCode::Char('h'));
+        assert_eq!(app.filtered_substances().len(), 1);
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(press(&mut app, KeyCode::Enter), Action::Continue);
+        assert_eq!((app.view(), app.form[0].as_str()), (View::Log, "Theanine"));
+
+        app.form[0] = String::from("caf");
+        app.field = 0;
+        assert_eq!(app.completion(), Some("Caffeine"));
+        press(&mut app, KeyCode::Tab);
+        press(&mut app, KeyCode::Tab);
+        for character in "100".chars() {
+            press(&mut app, KeyCode::Char(character));
+        }
+        assert_eq!(press(&mut app, KeyCode::Enter), Action::Submit);
+
+        let command = app.ingestion_command().unwrap();
+        assert_eq!((command.substance_id.as_str(), command.dosage_amount), ("Caffeine", Some(100.0)));
+        assert_eq!(command.dosage_unit, None);
+
+        press(&mut app, KeyCode::Esc);
+        assert_eq!(press(&mut app, KeyCode::Char('q')), Action::Quit);
     }
 
-    #[async_std::test]
-    async fn test_list_substances() {
-        let caffeine_fixture = sea_entity::substance::Model {
-            id: 78,
-            name: "caffeine".to_owned(),
+    #[test]
+    fn test_completions() {
+        use crate::cli::completions::{
+            dose_candidates, journal_options, substance_candidates, write_completions, CompletionShell, Completions,
+            JournalOptions, RecentDose,
         };
+        use chrono::TimeDelta;
+        use std::ffi::OsString;
+        use std::path::PathBuf;
 
-        // Create a mock in-memory SQLite database
-        let db = MockDatabase::new(DatabaseBackend::Sqlite)
-            .append_query_results([[caffeine_fixture.clone()]])
-            .append_exec_results([MockExecResult {
-                last_insert_id: 78,
-                rows_affected: 1,
-            }])
-            .into_connection();
+        let now = Local::now();
+        let substance = |alias: i32, name: &str| sea_entity::substance::Model {
+            id: format!("01J6NQ5SPZ2K9R3WDGCEHT4B8{}", alias),
+            alias,
+            name: name.to_owned(),
+            duration_minutes: None,
+            redose_interval_minutes: None,
+        };
+        let dose = |substance: &str, amount: f64, hours: i64| RecentDose {
+            substance: substance.to_owned(),
+            amount,
+            unit: String::from("mg"),
+            ingested_at: now - TimeDelta::hours(hours),
+        };
+        let substances = vec![substance(1, "Caffeine"), substance(2, "Cannabidiol"), substance(3, "Theanine")];
+        let doses = vec![dose("Caffeine", 100.0, 2), dose("Theanine", 200.0, 3), dose("Caffeine", 100.0, 5)];
 
-        let substances = list_substances(ListSubstance { limit: 10, page: 0 }, &db)
-            .await;
+        let candidates = substance_candidates("ca", &substances, &doses, now);
+        let values: Vec<_> = candidates.iter().map(|candidate| candidate.get_value().to_owned()).collect();
+        assert_eq!(values, ["Caffeine", "Cannabidiol"]);
+        assert_eq!(candidates[0].get_help().unwrap().to_string(), "last 100 mg 2h 0m ago");
+        assert!(candidates[1].get_help().is_none());
+
+        let values: Vec<_> = dose_candidates("", &doses, now)
+            .iter()
+            .map(|candidate| candidate.get_value().to_owned())
+            .collect();
+        assert_eq!(values, ["100", "200"]);
+
+        let words = ["--profile", "work", "-dd", "--database=/tmp/journal.db", "ingestion", "create", "Caf"];
+        let options = journal_options(words.map(OsString::from));
+        assert_eq!(options.profile.as_deref(), Some("work"));
+        assert_eq!(options.database, Some(PathBuf::from("/tmp/journal.db")));
+        assert_eq!(journal_options(["-pwork", "-c", "neuronek.toml"].map(OsString::from)), JournalOptions {
+            config: Some(PathBuf::from("neuronek.toml")),
+            database: None,
+            profile: Some(String::from("work")),
+        });
+
+        for shell in [CompletionShell::Bash, CompletionShell::Zsh, CompletionShell::Fish] {
+            for static_script in [false, true] {
+                let script = write_completions(Completions { shell, static_script }).unwrap();
+                assert!(String::from_utf8(script).unwrap().contains("neuronek"));
+            }
+        }
+    }
+
+    #[test]
+    fn test_ingestion_log() {
+        use crate::cli::ingestion::{format_log, relative_time};
+        use chrono::{TimeDelta, TimeZone};
+
+        let now = Local.with_ymd_and_hms(2024, 8, 20, 12, 0, 0).unwrap();
+        assert_eq!(relative_time(now - TimeDelta::seconds(30), now), "just now");
+        assert_eq!(relative_time(now - TimeDelta::minutes(5), now), "5m ago");
+        assert_eq!(relative_time(now - TimeDelta::hours(3), now), "3h ago");
+        assert_eq!(relative_time(now - TimeDelta::days(2), now), "2d ago");
+        assert_eq!(relative_time(now - TimeDelta::days(10), now), "2024-08-10");
+        assert_eq!(relative_time(now + TimeDelta::minutes(5), now), "in 5m");
+        assert_eq!(relative_time(now + TimeDelta::days(8), now), "2024-08-28");
+
+        let substance = |name: &str| sea_entity::substance::Model {
+            id: Ulid::new().to_string(),
+            alias: 1,
+            name: name.to_owned(),
+            duration_minutes: None,
+            redose_interval_minutes: None,
+        };
+        let ingestion = |alias: i32, minutes: i64, notes: Option<&str>| sea_entity::ingestion::Model {
+            id: Ulid::new().to_string(),
+            alias,
+            substance_id: String::new(),
+            route_of_administration: Some(String::from("oral")),
+            dosage_unit: String::from("mg"),
+            dosage_value: 100.0,
+            notes: notes.map(str::to_owned),
+            ingested_at: (now - TimeDelta::minutes(minutes)).fixed_offset(),
+            created_at: now.fixed_offset(),
+            updated_at: now.fixed_offset(),
+        };
+
+        let log = format_log(
+            &[
+                (ingestion(12, 5, Some("with breakfast")), substance("Caffeine")),
+                (ingestion(3, 300, None), substance("Theanine")),
+            ],
+            now,
+        );
+        assert_eq!(
+            log,
+            [
+                "  12      5m ago  Caffeine  100 mg oral  \"with breakfast\"",
+                "   3      5h ago  Theanine  100 mg oral",
+            ]
+        );
     }
 
     #[async_std::test]
-    async fn test_update_substance() {
+    async fn test_repeat_last_ingestion() {
+        use crate::cli::ingestion::{repeat_ingestion, Again};
+
         let db = use_memory_sqlite().await;
         setup_schema(&db).await;
+        for name in ["caffeine", "theanine"] {
+            create_substance(
+                CreateSubstance {
+                    name: name.to_owned(),
+                    duration: None,
+                    redose_interval: None,
+                },
+                &db,
+            )
+                .await
+                .unwrap();
+        }
 
-        create_substance(
-            CreateSubstance {
-                name: "caffeine".to_owned(),
+        let ingest = |substance_id: &str, dosage_amount: Option<f64>| CreateIngestion {
+            substance_id: substance_id.to_owned(),
+            dosage_unit: None,
+            dosage_amount,
+            ingestion_date: Local::now(),
+            route_of_administration: Some("oral".to_owned()),
+            notes: Some("with breakfast".to_owned()),
+            force: false,
+        };
+
+        let error = create_ingestion(ingest("theanine", None), &db).await.unwrap_err();
+        assert!(matches!(error, crate::error::Error::InvalidDose(_)));
+
+        create_ingestion(
+            CreateIngestion {
+                dosage_unit: Some("g".to_owned()),
+                ..ingest("caffeine", Some(0.2))
             },
             &db,
         )
             .await
-            .expect("Substance should be created");
-
-        let command = cli::substance::UpdateSubstance {
-            id: 1,
-            name: Option::from("Coffee".to_string()),
-        };
+            .unwrap();
+        let (fallback, _) = create_ingestion(ingest("caffeine", None), &db).await.unwrap();
+        assert_eq!((fallback.dosage_value, fallback.dosage_unit.as_str()), (0.2, "g"));
+        let error = create_ingestion(
+            CreateIngestion {
+                dosage_unit: Some("mg".to_owned()),
+                ..ingest("caffeine", None)
+            },
+            &db,
+        )
+            .await
+            .unwrap_err();
+        assert!(matches!(error, crate::error::Error::InvalidDose(_)));
 
-        let result = update_substance(command, &db).await;
-        assert!(result.is_ok());
+        let (again, substance, _) = repeat_ingestion(Again { substance: None, force: false }, &db)
+            .await
+            .unwrap();
+        assert_eq!(substance.name, "caffeine");
+        assert_eq!((again.dosage_value, again.dosage_unit.as_str()), (0.2, "g"));
+        assert_eq!((again.route_of_administration.as_deref(), again.notes), (Some("oral"), None));
 
-        let substance = result.unwrap();
-        assert_eq!(
-            substance,
-            sea_entity::substance::Model {
-                id: 1,
-                name: "Coffee".to_owned(),
-            }
-        );
+        let error = repeat_ingestion(
+            Again {
+                substance: Some("theanine".to_owned()),
+                force: false,
+            },
+            &db,
+        )
+            .await
+            .unwrap_err();
+        assert_eq!(error.exit_code(), 64);
     }
 
     #[async_std::test]
-    async fn test_create_ingestion() {
-        let caffeine_ingestion = sea_entity::ingestion::Model {
-            id: 1,
-            substance_id: 1,
-            dosage_unit: "mg".to_string(),
-            dosage_value: 20.0,
-            ingested_at: Utc::now().into(),
-            created_at: Utc::now().into(),
-            updated_at: Utc::now().into(),
-        };
+    async fn test_last_ingestion_across_timezone_offsets() {
+        use crate::cli::completions::query_journal;
+        use crate::cli::ingestion::{find_last_ingestion, find_latest_ingestions};
+        use sea_orm::{ActiveModelTrait, ActiveValue};
 
         let db = use_memory_sqlite().await;
         setup_schema(&db).await;
-
         create_substance(
             CreateSubstance {
                 name: "caffeine".to_owned(),
+                duration: None,
+                redose_interval: None,
             },
             &db,
         )
             .await
-            .expect("Substance should be created");
+            .unwrap();
 
-        let command = CreateIngestion {
-            substance_id: caffeine_ingestion.substance_id,
-            dosage_unit: caffeine_ingestion.dosage_unit.clone(),
-            dosage_amount: caffeine_ingestion.dosage_value,
-            ingestion_date: DateTime::<Local>::default(),
-        };
+        // 10:00 UTC is later than 12:00 at +05:00 even though it sorts before it as text.
+        let ingestions = [(100.0, "2024-05-01T10:00:00+00:00"), (200.0, "2024-05-01T12:00:00+05:00")];
+        for (dosage_amount, ingested_at) in ingestions {
+            let (ingestion, _) = create_ingestion(
+                CreateIngestion {
+                    substance_id: "caffeine".to_owned(),
+                    dosage_unit: Some("mg".to_owned()),
+                    dosage_amount: Some(dosage_amount),
+                    ingestion_date: Local::now(),
+                    route_of_administration: None,
+                    notes: None,
+                    force: false,
+                },
+                &db,
+            )
+                .await
+                .unwrap();
+            sea_entity::ingestion::ActiveModel {
+                ingested_at: ActiveValue::Set(DateTime::parse_from_rfc3339(ingested_at).unwrap()),
+                ..ingestion.into()
+            }
+                .update(&db)
+                .await
+                .unwrap();
+        }
 
-        let result = create_ingestion(command, &db).await;
-        assert!(result.is_ok());
+        let (last_ingestion, _) = find_last_ingestion(None, &db).await.unwrap().unwrap();
+        assert_eq!(last_ingestion.dosage_value, 100.0);
 
-        let model = result.unwrap();
-        assert_eq!(model.substance_id, 1);
-        assert_eq!(model.dosage_unit, "mg");
-        assert_eq!(model.dosage_value, 20.0);
+        let latest = find_latest_ingestions(&IngestionFilter::default(), 1, &db).await.unwrap();
+        assert_eq!(latest.iter().map(|(ingestion, _)| ingestion.dosage_value).collect::<Vec<_>>(), [100.0]);
+
+        let (_, doses) = query_journal(&db).await.unwrap();
+        assert_eq!(doses.iter().map(|dose| dose.amount).collect::<Vec<_>>(), [100.0, 200.0]);
     }
 }